@@ -0,0 +1,290 @@
+//! Token masks for constrained generation. A pattern is compiled to a DFA
+//! over bytes, and the tokens allowed in each state are found once by
+//! running the decoded bytes of every token through it.
+
+use ahash::AHashMap;
+use magnus::{function, method, Error, RModule, Ruby};
+use regex_automata::dfa::{dense, Automaton, StartKind};
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use regex_automata::{Anchored, MatchKind};
+use regex_syntax::hir::{
+    Capture, Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Hir, HirKind,
+    Repetition,
+};
+use tk::parallelism::MaybeParallelIterator;
+
+use super::parallelism;
+use super::prefix_index::RbPrefixIndex;
+use super::ruby::{GvlExt, Interrupt};
+use super::tokenizer::RbTokenizer;
+use super::RbResult;
+
+/// Limits on the memory of the DFA, so patterns with large repetitions or
+/// Unicode classes raise instead of taking all memory.
+const DFA_SIZE_LIMIT: usize = 256 << 20;
+const DETERMINIZE_SIZE_LIMIT: usize = 256 << 20;
+
+/// Characters that are escaped in JSON strings.
+const JSON_ESCAPED: [(char, char); 3] = [('\0', '\u{1F}'), ('"', '"'), ('\\', '\\')];
+
+#[magnus::wrap(class = "Tokenizers::Constraint", frozen_shareable)]
+pub struct RbConstraint {
+    /// Allowed tokens of each state, sorted by id, with the state after them.
+    transitions: Vec<Vec<(u32, usize)>>,
+    /// Whether the text so far matches in each state.
+    finals: Vec<bool>,
+}
+
+impl RbConstraint {
+    pub fn regex(ruby: &Ruby, pattern: String, tokenizer: &RbTokenizer) -> RbResult<Self> {
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .start_kind(StartKind::Anchored)
+                    .match_kind(MatchKind::All)
+                    .dfa_size_limit(Some(DFA_SIZE_LIMIT))
+                    .determinize_size_limit(Some(DETERMINIZE_SIZE_LIMIT)),
+            )
+            .build(&pattern)
+            .map_err(|e| Error::new(ruby.exception_arg_error(), e.to_string()))?;
+        // special tokens aren't text, so a pattern can't match them
+        let vocab = tokenizer.decoded_vocab(ruby, true)?;
+        ruby.detach(|interrupt| parallelism::install(None, || Self::build(&dfa, &vocab, interrupt)))
+    }
+
+    /// Pattern for the contents of a JSON string whose value matches
+    /// `pattern`, with the characters JSON escapes matched in escaped form.
+    pub fn json_string_regex(ruby: &Ruby, pattern: String) -> RbResult<String> {
+        let hir = regex_syntax::parse(&pattern)
+            .map_err(|e| Error::new(ruby.exception_arg_error(), e.to_string()))?;
+        Ok(json_escaped(hir).to_string())
+    }
+
+    fn build(
+        dfa: &dense::DFA<Vec<u32>>,
+        vocab: &[Option<Vec<u8>>],
+        interrupt: &Interrupt,
+    ) -> tk::Result<Self> {
+        let start = dfa.start_state(&start::Config::new().anchored(Anchored::Yes))?;
+        let mut states = vec![start];
+        let mut indices = AHashMap::new();
+        indices.insert(start, 0);
+        let mut transitions: Vec<Vec<(u32, usize)>> = Vec::new();
+
+        // states are numbered in the order they're found, so the start is 0
+        while transitions.len() < states.len() {
+            interrupt.check()?;
+            let state = states[transitions.len()];
+            let next: Vec<(u32, StateID)> = (0..vocab.len())
+                .into_maybe_par_iter()
+                .filter_map(|id| {
+                    let bytes = vocab[id].as_deref()?;
+                    walk(dfa, state, bytes).map(|next| (id as u32, next))
+                })
+                .collect();
+            let next = next
+                .into_iter()
+                .map(|(id, next)| {
+                    let index = *indices.entry(next).or_insert_with(|| {
+                        states.push(next);
+                        states.len() - 1
+                    });
+                    (id, index)
+                })
+                .collect();
+            transitions.push(next);
+        }
+        let finals: Vec<bool> = states
+            .iter()
+            .map(|&state| dfa.is_match_state(dfa.next_eoi_state(state)))
+            .collect();
+        Ok(Self::new(transitions, finals))
+    }
+
+    /// Constraint for text that starts with `prefix`. The prefix can be
+    /// split between tokens, and the last of them can go past it.
+    pub fn prefix(index: &RbPrefixIndex, prefix: &[u8]) -> Self {
+        // one state for each position in the prefix, then one for the rest
+        let rest = prefix.len();
+        let mut transitions = Vec::with_capacity(rest + 1);
+        for start in 0..rest {
+            let remaining = &prefix[start..];
+            let mut next: Vec<(u32, usize)> = index
+                .with_prefix(remaining)
+                .into_iter()
+                .map(|id| (id, rest))
+                .collect();
+            next.extend(
+                index
+                    .prefixes_of(remaining)
+                    .into_iter()
+                    .filter(|&(_, len)| len < remaining.len())
+                    .map(|(id, len)| (id, start + len)),
+            );
+            next.sort_unstable();
+            transitions.push(next);
+        }
+        transitions.push(index.ids().into_iter().map(|id| (id, rest)).collect());
+        let mut finals = vec![false; rest + 1];
+        finals[rest] = true;
+        Self::new(transitions, finals)
+    }
+
+    fn new(mut transitions: Vec<Vec<(u32, usize)>>, finals: Vec<bool>) -> Self {
+        // drop tokens that lead to states where no tokens can finish a match
+        let mut live = finals.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, next) in transitions.iter().enumerate() {
+                if !live[index] && next.iter().any(|&(_, next)| live[next]) {
+                    live[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        for next in &mut transitions {
+            next.retain(|&(_, next)| live[next]);
+        }
+
+        Self {
+            transitions,
+            finals,
+        }
+    }
+
+    fn transitions(&self, ruby: &Ruby, state: usize) -> RbResult<&[(u32, usize)]> {
+        self.transitions
+            .get(state)
+            .map(Vec::as_slice)
+            .ok_or_else(|| Error::new(ruby.exception_arg_error(), "Invalid state"))
+    }
+
+    pub fn allowed_token_ids(ruby: &Ruby, rb_self: &Self, state: usize) -> RbResult<Vec<u32>> {
+        Ok(rb_self
+            .transitions(ruby, state)?
+            .iter()
+            .map(|&(id, _)| id)
+            .collect())
+    }
+
+    pub fn advance(ruby: &Ruby, rb_self: &Self, state: usize, id: u32) -> RbResult<Option<usize>> {
+        let transitions = rb_self.transitions(ruby, state)?;
+        Ok(transitions
+            .binary_search_by_key(&id, |&(id, _)| id)
+            .ok()
+            .map(|index| transitions[index].1))
+    }
+
+    pub fn is_final(ruby: &Ruby, rb_self: &Self, state: usize) -> RbResult<bool> {
+        rb_self
+            .finals
+            .get(state)
+            .copied()
+            .ok_or_else(|| Error::new(ruby.exception_arg_error(), "Invalid state"))
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.transitions.len()
+    }
+}
+
+/// State after the bytes of a token, unless they can't be part of a match.
+/// Tokens without bytes are never allowed, since they don't make progress.
+fn walk(dfa: &dense::DFA<Vec<u32>>, mut state: StateID, bytes: &[u8]) -> Option<StateID> {
+    if bytes.is_empty() {
+        return None;
+    }
+    for &byte in bytes {
+        state = dfa.next_state(state, byte);
+        if dfa.is_dead_state(state) {
+            return None;
+        }
+    }
+    Some(state)
+}
+
+/// Matches the JSON encoding of what `hir` matches. Bytes that aren't UTF-8
+/// can't be in JSON text, so they never match.
+fn json_escaped(hir: Hir) -> Hir {
+    match hir.into_kind() {
+        HirKind::Empty => Hir::empty(),
+        HirKind::Look(look) => Hir::look(look),
+        HirKind::Literal(literal) => match std::str::from_utf8(&literal.0) {
+            Ok(text) => Hir::literal(json_escape(text).into_bytes()),
+            Err(_) => Hir::fail(),
+        },
+        HirKind::Class(class) => {
+            // characters that don't need escaping stay in the class
+            let (class, escaped): (Class, Vec<char>) = match class {
+                Class::Unicode(mut class) => {
+                    let mut escaped = ClassUnicode::new(
+                        JSON_ESCAPED
+                            .iter()
+                            .map(|&(start, end)| ClassUnicodeRange::new(start, end)),
+                    );
+                    escaped.intersect(&class);
+                    class.difference(&escaped);
+                    let escaped = escaped.iter().flat_map(|r| r.start()..=r.end()).collect();
+                    (Class::Unicode(class), escaped)
+                }
+                Class::Bytes(mut class) => {
+                    let mut escaped = ClassBytes::new(
+                        JSON_ESCAPED
+                            .iter()
+                            .map(|&(start, end)| ClassBytesRange::new(start as u8, end as u8)),
+                    );
+                    escaped.intersect(&class);
+                    class.difference(&escaped);
+                    let escaped = escaped
+                        .iter()
+                        .flat_map(|r| r.start()..=r.end())
+                        .map(char::from)
+                        .collect();
+                    (Class::Bytes(class), escaped)
+                }
+            };
+            let mut alternatives = vec![Hir::class(class)];
+            alternatives.extend(
+                escaped
+                    .into_iter()
+                    .map(|c| Hir::literal(json_escape(&c.to_string()).into_bytes())),
+            );
+            Hir::alternation(alternatives)
+        }
+        HirKind::Repetition(repetition) => Hir::repetition(Repetition {
+            sub: Box::new(json_escaped(*repetition.sub)),
+            ..repetition
+        }),
+        // groups are left out, since their names could clash with other patterns
+        HirKind::Capture(Capture { sub, .. }) => json_escaped(*sub),
+        HirKind::Concat(subs) => Hir::concat(subs.into_iter().map(json_escaped).collect()),
+        HirKind::Alternation(subs) => {
+            Hir::alternation(subs.into_iter().map(json_escaped).collect())
+        }
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let json = serde_json::to_string(text).unwrap();
+    json[1..json.len() - 1].to_string()
+}
+
+pub fn init_constraint(ruby: &Ruby, module: &RModule) -> RbResult<()> {
+    let class = module.define_class("Constraint", ruby.class_object())?;
+    class.define_singleton_method("_regex", function!(RbConstraint::regex, 2))?;
+    class.define_singleton_method(
+        "_json_string_regex",
+        function!(RbConstraint::json_string_regex, 1),
+    )?;
+    class.define_method(
+        "allowed_token_ids",
+        method!(RbConstraint::allowed_token_ids, 1),
+    )?;
+    class.define_method("advance", method!(RbConstraint::advance, 2))?;
+    class.define_method("final?", method!(RbConstraint::is_final, 1))?;
+    class.define_method("num_states", method!(RbConstraint::num_states, 0))?;
+
+    Ok(())
+}
@@ -38,6 +38,27 @@ impl RbDecoder {
     pub fn decode(&self, tokens: Vec<String>) -> RbResult<String> {
         self.decoder.decode(tokens).map_err(RbError::from)
     }
+
+    /// Bytes that `token` adds to a decoded text when it follows other tokens,
+    /// or when it's the first token with `start`.
+    pub fn token_bytes(&self, token: &str, start: bool) -> tk::Result<Vec<u8>> {
+        let RbDecoderWrapper::Wrapped(ref wrap) = self.decoder;
+        let mut bytes = TokenBytes {
+            bytes: token.as_bytes().to_vec(),
+            fused: false,
+            start,
+        };
+        bytes.apply(&wrap.read().unwrap())?;
+        Ok(bytes.bytes)
+    }
+
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "Decoder")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "Decoder")
+    }
 }
 
 macro_rules! getter {
@@ -286,6 +307,95 @@ impl Decoder for RbDecoderWrapper {
     }
 }
 
+/// Decodes a single token without the lossy conversions to strings, so
+/// partial characters keep their bytes.
+struct TokenBytes {
+    bytes: Vec<u8>,
+    /// Whether tokens were joined, after which steps like `Strip` only change
+    /// the start and the end of the text.
+    fused: bool,
+    /// Whether the token starts the text, where steps like `Metaspace` drop
+    /// the leading space.
+    start: bool,
+}
+
+impl TokenBytes {
+    fn apply(&mut self, decoder: &DecoderWrapper) -> tk::Result<()> {
+        match decoder {
+            DecoderWrapper::Sequence(sequence) => {
+                for decoder in sequence.get_decoders() {
+                    self.apply(decoder)?;
+                }
+            }
+            DecoderWrapper::ByteLevel(_) => {
+                if let Ok(text) = std::str::from_utf8(&self.bytes) {
+                    // tokens with other characters are kept as is
+                    if let Some(bytes) = text.chars().map(byte_level_byte).collect() {
+                        self.bytes = bytes;
+                    }
+                }
+                self.fused = true;
+            }
+            DecoderWrapper::ByteFallback(_) => {
+                if self.bytes.len() == 6 && self.bytes.starts_with(b"<0x") && self.bytes[5] == b'>'
+                {
+                    let byte = std::str::from_utf8(&self.bytes[3..5])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if let Some(byte) = byte {
+                        self.bytes = vec![byte];
+                    }
+                }
+            }
+            DecoderWrapper::Fuse(_) => self.fused = true,
+            DecoderWrapper::Strip(strip) if self.fused => {
+                // the end of the text isn't known yet, so only the start is cut
+                if self.start {
+                    let content = strip.content.to_string();
+                    for _ in 0..strip.start {
+                        match self.bytes.strip_prefix(content.as_bytes()) {
+                            Some(rest) => self.bytes = rest.to_vec(),
+                            None => break,
+                        }
+                    }
+                }
+            }
+            decoder => {
+                if let Ok(token) = String::from_utf8(self.bytes.clone()) {
+                    // surround the token so it's decoded like one in the middle
+                    // of a text, or put it first for the start
+                    let edge = "\u{1}".to_string();
+                    let (tokens, index) = if self.start {
+                        (vec![token, edge], 0)
+                    } else {
+                        (vec![edge.clone(), token, edge], 1)
+                    };
+                    let len = tokens.len();
+                    let mut tokens = decoder.decode_chain(tokens)?;
+                    self.bytes = if tokens.len() == len {
+                        tokens.swap_remove(index).into_bytes()
+                    } else {
+                        Vec::new()
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Byte for a character of the alphabet of `ByteLevel`, which maps printable
+/// bytes to themselves and the others to characters from U+0100.
+fn byte_level_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        0x21..=0x7E | 0xA1..=0xAC | 0xAE..=0xFF => Some(c as u8),
+        n @ 0x100..=0x120 => Some((n - 0x100) as u8),
+        n @ 0x121..=0x142 => Some((n - 0x121 + 0x7F) as u8),
+        0x143 => Some(0xAD),
+        _ => None,
+    }
+}
+
 unsafe impl TypedData for RbDecoder {
     fn class(ruby: &Ruby) -> RClass {
         static CLASS: Lazy<RClass> = Lazy::new(|ruby| {
@@ -297,8 +407,9 @@ unsafe impl TypedData for RbDecoder {
     }
 
     fn data_type() -> &'static DataType {
-        static DATA_TYPE: DataType =
-            data_type_builder!(RbDecoder, "Tokenizers::Decoders::Decoder").build();
+        static DATA_TYPE: DataType = data_type_builder!(RbDecoder, "Tokenizers::Decoders::Decoder")
+            .frozen_shareable()
+            .build();
         &DATA_TYPE
     }
 
@@ -368,6 +479,8 @@ unsafe impl TypedData for RbDecoder {
 pub fn init_decoders(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let decoder = module.define_class("Decoder", ruby.class_object())?;
     decoder.define_method("decode", method!(RbDecoder::decode, 1))?;
+    decoder.define_singleton_method("_load", function!(RbDecoder::load, 1))?;
+    decoder.define_method("_dump", method!(RbDecoder::dump, 1))?;
 
     let class = module.define_class("BPEDecoder", decoder)?;
     class.define_singleton_method("_new", function!(RbBPEDecoder::new, 1))?;
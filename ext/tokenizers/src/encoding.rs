@@ -1,25 +1,183 @@
-use magnus::{method, Module, RArray, RModule, Ruby};
-use tk::{Encoding, Offsets};
+use magnus::{
+    function, method, prelude::*, value::Lazy, Error, RArray, RClass, RHash, RModule, RString,
+    Range, Ruby, TryConvert, Value,
+};
+use serde::{Deserialize, Serialize};
+use tk::{Encoding, Offsets, PaddingDirection, TruncationDirection};
 
-use super::RbResult;
+use super::packed::{DType, ModelInput};
+use super::truncation::select;
+use super::utils::{marshal_dump, marshal_load, symbol_or_string};
+use super::{RbError, RbResult, TOKENIZERS};
 
-#[magnus::wrap(class = "Tokenizers::Encoding")]
-#[repr(transparent)]
+static TOKEN: Lazy<RClass> =
+    Lazy::new(|ruby| ruby.get_inner(&TOKENIZERS).const_get("Token").unwrap());
+
+static WORD: Lazy<RClass> =
+    Lazy::new(|ruby| ruby.get_inner(&TOKENIZERS).const_get("Word").unwrap());
+
+#[magnus::wrap(class = "Tokenizers::Encoding", frozen_shareable)]
 pub struct RbEncoding {
     pub encoding: Encoding,
+    /// Number of tokens before truncation, when known.
+    pub original_length: Option<usize>,
+    /// Original and normalized text of each sequence, when kept.
+    pub texts: Vec<(String, String)>,
+}
+
+/// Marshal data of an encoding, with what Tokenizers doesn't keep.
+#[derive(Serialize, Deserialize)]
+struct SavedEncoding {
+    encoding: Encoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    texts: Vec<(String, String)>,
 }
 
 impl From<Encoding> for RbEncoding {
     fn from(v: Encoding) -> Self {
-        Self { encoding: v }
+        Self {
+            encoding: v,
+            original_length: None,
+            texts: Vec::new(),
+        }
     }
 }
 
 impl RbEncoding {
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        let saved = SavedEncoding {
+            encoding: self.encoding.clone(),
+            original_length: self.original_length,
+            texts: self.texts.clone(),
+        };
+        marshal_dump(&saved, "Encoding")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        let saved: SavedEncoding = marshal_load(&data, "Encoding")?;
+        Ok(Self {
+            encoding: saved.encoding,
+            original_length: saved.original_length,
+            texts: saved.texts,
+        })
+    }
+
+    pub fn pack(ruby: &Ruby, rb_self: &Self, name: Value, dtype: Value) -> RbResult<RString> {
+        let name = ModelInput::new(ruby, name)?;
+        let dtype = DType::new(ruby, dtype)?;
+        let mut buffer = Vec::with_capacity(rb_self.encoding.len() * dtype.size());
+        for value in name.row(&rb_self.encoding) {
+            dtype.push(&mut buffer, value).map_err(RbError::from)?;
+        }
+        Ok(ruby.str_from_slice(&buffer))
+    }
+
+    pub fn merge(encodings: RArray, growing_offsets: bool) -> RbResult<Self> {
+        let encodings = encodings
+            .into_iter()
+            .map(|value| <&RbEncoding>::try_convert(value).map(|e| e.encoding.clone()))
+            .collect::<RbResult<Vec<_>>>()?;
+        Ok(Encoding::merge(encodings, growing_offsets).into())
+    }
+
+    pub fn pad(
+        ruby: &Ruby,
+        rb_self: &Self,
+        length: usize,
+        direction: Value,
+        pad_id: u32,
+        pad_type_id: u32,
+        pad_token: String,
+    ) -> RbResult<Self> {
+        let direction = match symbol_or_string(direction)?.as_str() {
+            "left" => PaddingDirection::Left,
+            "right" => PaddingDirection::Right,
+            _ => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The direction value must be 'left' or 'right'",
+                ))
+            }
+        };
+        let mut encoding = rb_self.encoding.clone();
+        encoding.pad(length, pad_id, pad_type_id, &pad_token, direction);
+        Ok(Self {
+            encoding,
+            original_length: rb_self.original_length,
+            texts: rb_self.texts.clone(),
+        })
+    }
+
+    pub fn truncate(
+        ruby: &Ruby,
+        rb_self: &Self,
+        max_length: usize,
+        stride: usize,
+        direction: Value,
+    ) -> RbResult<Self> {
+        let direction = match symbol_or_string(direction)?.as_str() {
+            "left" => TruncationDirection::Left,
+            "right" => TruncationDirection::Right,
+            _ => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The direction value must be 'left' or 'right'",
+                ))
+            }
+        };
+        if max_length > 0 && stride >= max_length {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "stride must be less than max_length",
+            ));
+        }
+        let mut encoding = rb_self.encoding.clone();
+        encoding.truncate(max_length, stride, direction);
+        Ok(Self {
+            encoding,
+            original_length: Some(rb_self.original_length()),
+            texts: rb_self.texts.clone(),
+        })
+    }
+
+    pub fn slice(&self, range: Range) -> RbResult<Self> {
+        let (start, length) = range.beg_len(self.encoding.len())?;
+        Ok(select(&self.encoding, &[start..start + length]).into())
+    }
+
     pub fn get_n_sequences(&self) -> usize {
         self.encoding.n_sequences()
     }
 
+    pub fn is_truncated(&self) -> bool {
+        !self.encoding.get_overflowing().is_empty()
+    }
+
+    pub fn original_length(&self) -> usize {
+        // without truncation, all tokens but padding are kept
+        self.original_length.unwrap_or_else(|| {
+            self.encoding
+                .get_attention_mask()
+                .iter()
+                .filter(|&&mask| mask == 1)
+                .count()
+        })
+    }
+
+    pub fn num_overflowing(&self) -> usize {
+        self.encoding.get_overflowing().len()
+    }
+
+    pub fn original_text(&self, sequence_index: usize) -> Option<String> {
+        self.texts.get(sequence_index).map(|(text, _)| text.clone())
+    }
+
+    pub fn normalized_text(&self, sequence_index: usize) -> Option<String> {
+        self.texts.get(sequence_index).map(|(_, text)| text.clone())
+    }
+
     pub fn get_ids(&self) -> Vec<u32> {
         self.encoding.get_ids().to_vec()
     }
@@ -63,6 +221,98 @@ impl RbEncoding {
         )
     }
 
+    pub fn to_a(ruby: &Ruby, rb_self: &Self) -> RbResult<RArray> {
+        let encoding = &rb_self.encoding;
+        let class = ruby.get_inner(&TOKEN);
+        let sequence_ids = encoding.get_sequence_ids();
+        let tokens = ruby.ary_new_capa(encoding.len());
+        for (i, sequence_id) in sequence_ids.into_iter().enumerate() {
+            let token: Value = class.new_instance((
+                encoding.get_ids()[i],
+                encoding.get_tokens()[i].as_str(),
+                encoding.get_offsets()[i],
+                encoding.get_word_ids()[i],
+                sequence_id,
+                encoding.get_type_ids()[i],
+                encoding.get_special_tokens_mask()[i] == 1,
+            ))?;
+            tokens.push(token)?;
+        }
+        Ok(tokens)
+    }
+
+    pub fn words(ruby: &Ruby, rb_self: &Self) -> RbResult<RArray> {
+        let encoding = &rb_self.encoding;
+        let sequence_ids = encoding.get_sequence_ids();
+        let word_ids = encoding.get_word_ids();
+        let offsets = encoding.get_offsets();
+        let class = ruby.get_inner(&WORD);
+        let words = ruby.ary_new();
+        let mut start = 0;
+        while start < encoding.len() {
+            // a word is a run of tokens with the same word id
+            let key = (sequence_ids[start], word_ids[start]);
+            let mut end = start + 1;
+            while end < encoding.len() && (sequence_ids[end], word_ids[end]) == key {
+                end += 1;
+            }
+            if let (sequence_id, Some(word_id)) = key {
+                let word: Value = class.new_instance((
+                    word_id,
+                    sequence_id,
+                    ruby.range_new(start, end, true)?,
+                    (offsets[start].0, offsets[end - 1].1),
+                ))?;
+                words.push(word)?;
+            }
+            start = end;
+        }
+        Ok(words)
+    }
+
+    pub fn sequence(&self, sequence_index: usize) -> Option<Self> {
+        let indices: Vec<usize> = self
+            .encoding
+            .get_sequence_ids()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, id)| *id == Some(sequence_index))
+            .map(|(i, _)| i)
+            .collect();
+        let (first, last) = (*indices.first()?, *indices.last()?);
+        let mut encoding = select(&self.encoding, &[first..last + 1]);
+        encoding.set_sequence_id(0);
+        Some(Self {
+            encoding,
+            original_length: None,
+            texts: self
+                .texts
+                .get(sequence_index)
+                .cloned()
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    pub fn to_h(ruby: &Ruby, rb_self: &Self) -> RbResult<RHash> {
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("ids"), rb_self.get_ids())?;
+        hash.aset(ruby.to_symbol("tokens"), rb_self.get_tokens())?;
+        hash.aset(ruby.to_symbol("offsets"), rb_self.get_offsets())?;
+        hash.aset(ruby.to_symbol("word_ids"), rb_self.get_word_ids())?;
+        hash.aset(ruby.to_symbol("sequence_ids"), rb_self.get_sequence_ids())?;
+        hash.aset(ruby.to_symbol("type_ids"), rb_self.get_type_ids())?;
+        hash.aset(
+            ruby.to_symbol("special_tokens_mask"),
+            rb_self.get_special_tokens_mask(),
+        )?;
+        hash.aset(
+            ruby.to_symbol("attention_mask"),
+            rb_self.get_attention_mask(),
+        )?;
+        Ok(hash)
+    }
+
     pub fn word_to_tokens(&self, word_index: u32, sequence_index: usize) -> Option<(usize, usize)> {
         self.encoding.word_to_tokens(word_index, sequence_index)
     }
@@ -96,7 +346,19 @@ impl RbEncoding {
 
 pub fn init_encoding(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let class = module.define_class("Encoding", ruby.class_object())?;
+    class.define_singleton_method("_load", function!(RbEncoding::load, 1))?;
+    class.define_method("_dump", method!(RbEncoding::dump, 1))?;
+    class.define_method("_pack", method!(RbEncoding::pack, 2))?;
+    class.define_singleton_method("_merge", function!(RbEncoding::merge, 2))?;
+    class.define_method("_pad", method!(RbEncoding::pad, 5))?;
+    class.define_method("_truncate", method!(RbEncoding::truncate, 3))?;
+    class.define_method("slice", method!(RbEncoding::slice, 1))?;
     class.define_method("n_sequences", method!(RbEncoding::get_n_sequences, 0))?;
+    class.define_method("truncated?", method!(RbEncoding::is_truncated, 0))?;
+    class.define_method("original_length", method!(RbEncoding::original_length, 0))?;
+    class.define_method("num_overflowing", method!(RbEncoding::num_overflowing, 0))?;
+    class.define_method("_original_text", method!(RbEncoding::original_text, 1))?;
+    class.define_method("_normalized_text", method!(RbEncoding::normalized_text, 1))?;
     class.define_method("ids", method!(RbEncoding::get_ids, 0))?;
     class.define_method("tokens", method!(RbEncoding::get_tokens, 0))?;
     class.define_method("word_ids", method!(RbEncoding::get_word_ids, 0))?;
@@ -109,6 +371,10 @@ pub fn init_encoding(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     )?;
     class.define_method("attention_mask", method!(RbEncoding::get_attention_mask, 0))?;
     class.define_method("overflowing", method!(RbEncoding::get_overflowing, 0))?;
+    class.define_method("to_a", method!(RbEncoding::to_a, 0))?;
+    class.define_method("to_h", method!(RbEncoding::to_h, 0))?;
+    class.define_method("words", method!(RbEncoding::words, 0))?;
+    class.define_method("sequence", method!(RbEncoding::sequence, 1))?;
     class.define_method("_word_to_tokens", method!(RbEncoding::word_to_tokens, 2))?;
     class.define_method("_word_to_chars", method!(RbEncoding::word_to_chars, 2))?;
     class.define_method(
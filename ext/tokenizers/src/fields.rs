@@ -0,0 +1,137 @@
+//! Encoding of records with several fields that share a token budget.
+
+use tk::Encoding;
+
+use super::truncation::select_as;
+
+/// Splits `budget` tokens between fields. Fields with a higher priority keep
+/// all their tokens first, and fields with the same priority are cut in
+/// proportion to their length.
+pub fn allocate(lengths: &[usize], priorities: &[i64], budget: usize) -> Vec<usize> {
+    let mut allocation = vec![0; lengths.len()];
+    let mut levels = priorities.to_vec();
+    levels.sort_unstable_by(|a, b| b.cmp(a));
+    levels.dedup();
+
+    let mut remaining = budget;
+    for level in levels {
+        let fields: Vec<usize> = (0..lengths.len())
+            .filter(|&i| priorities[i] == level)
+            .collect();
+        let total: usize = fields.iter().map(|&i| lengths[i]).sum();
+        if total <= remaining {
+            for &i in &fields {
+                allocation[i] = lengths[i];
+            }
+            remaining -= total;
+            continue;
+        }
+
+        // largest remainder method, so the whole budget is used
+        let mut remainders = Vec::with_capacity(fields.len());
+        let mut used = 0;
+        for &i in &fields {
+            let share = remaining * lengths[i];
+            allocation[i] = share / total;
+            used += allocation[i];
+            remainders.push((share % total, i));
+        }
+        remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        for &(_, i) in remainders.iter().take(remaining - used) {
+            allocation[i] += 1;
+        }
+        break;
+    }
+    allocation
+}
+
+enum Piece {
+    Field(usize),
+    Special(u32, String),
+}
+
+/// Layout of the fields and special tokens of an encoding, written like
+/// `"[CLS] $title [SEP] $body:1 [SEP]"`. Fields use their position in the
+/// record as type id, and special tokens the type id of the field before
+/// them, unless one is given after a colon.
+pub struct FieldTemplate(Vec<(Piece, u32)>);
+
+impl FieldTemplate {
+    pub fn parse<F>(template: &str, names: &[String], token_to_id: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Option<u32>,
+    {
+        let mut pieces = Vec::new();
+        let mut seen = vec![false; names.len()];
+        let mut type_id = 0;
+        for part in template.split_whitespace() {
+            let (name, explicit) = match part.rsplit_once(':') {
+                Some((name, id)) if !name.is_empty() && id.parse::<u32>().is_ok() => {
+                    (name, id.parse().ok())
+                }
+                _ => (part, None),
+            };
+            if let Some(field) = name.strip_prefix('$') {
+                let index = names
+                    .iter()
+                    .position(|n| n == field)
+                    .ok_or_else(|| format!("Unknown field in template: {}", field))?;
+                if seen[index] {
+                    return Err(format!(
+                        "Field appears more than once in template: {}",
+                        field
+                    ));
+                }
+                seen[index] = true;
+                type_id = explicit.unwrap_or(index as u32);
+                pieces.push((Piece::Field(index), type_id));
+            } else {
+                let id = token_to_id(name)
+                    .ok_or_else(|| format!("Unknown special token in template: {}", name))?;
+                pieces.push((
+                    Piece::Special(id, name.to_string()),
+                    explicit.unwrap_or(type_id),
+                ));
+            }
+        }
+        if let Some(index) = seen.iter().position(|seen| !seen) {
+            return Err(format!("Missing field in template: {}", names[index]));
+        }
+        Ok(Self(pieces))
+    }
+
+    pub fn num_special_tokens(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|(piece, _)| matches!(piece, Piece::Special(..)))
+            .count()
+    }
+
+    /// Joins the fields, keeping the first `allocation[i]` tokens of field
+    /// `i`. Each field is its own sequence in the result.
+    pub fn apply(&self, fields: &[Encoding], allocation: &[usize]) -> Encoding {
+        let mut encoding = Encoding::default();
+        for (piece, type_id) in &self.0 {
+            let piece = match piece {
+                Piece::Field(index) => {
+                    let mut field = select_as(&fields[*index], &[0..allocation[*index]], *type_id);
+                    field.set_sequence_id(*index);
+                    field
+                }
+                Piece::Special(id, token) => Encoding::new(
+                    vec![*id],
+                    vec![*type_id],
+                    vec![token.clone()],
+                    vec![None],
+                    vec![(0, 0)],
+                    vec![1],
+                    vec![1],
+                    vec![],
+                    Default::default(),
+                ),
+            };
+            encoding.merge_with(piece, false);
+        }
+        encoding
+    }
+}
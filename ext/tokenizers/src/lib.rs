@@ -2,16 +2,24 @@
 
 extern crate tokenizers as tk;
 
+mod constraint;
 mod decoders;
 mod encoding;
 mod error;
+mod fields;
 mod models;
 mod normalizers;
+mod packed;
+mod parallelism;
 mod pre_tokenizers;
+mod prefix_index;
 mod processors;
 mod ruby;
+mod stop_sequences;
+mod text;
 mod tokenizer;
 mod trainers;
+mod truncation;
 mod utils;
 
 use error::RbError;
@@ -50,11 +58,16 @@ static TRAINERS: Lazy<RModule> =
 
 #[magnus::init(name = "tokenizers")]
 fn init(ruby: &Ruby) -> RbResult<()> {
+    // wrapped types are thread-safe, so methods can be called from any Ractor
+    unsafe { rb_sys::rb_ext_ractor_safe(true) };
+
     let module = ruby.define_module("Tokenizers")?;
 
     let class = module.define_class("Regex", ruby.class_object())?;
     class.define_singleton_method("new", function!(RbRegex::new, 1))?;
 
+    parallelism::init_parallelism(&module)?;
+
     let models = module.define_module("Models")?;
     let pre_tokenizers = module.define_module("PreTokenizers")?;
     let decoders = module.define_module("Decoders")?;
@@ -64,6 +77,9 @@ fn init(ruby: &Ruby) -> RbResult<()> {
 
     tokenizer::init_tokenizer(ruby, &module)?;
     encoding::init_encoding(ruby, &module)?;
+    constraint::init_constraint(ruby, &module)?;
+    prefix_index::init_prefix_index(ruby, &module)?;
+    stop_sequences::init_stop_sequences(ruby, &module)?;
     models::init_models(ruby, &models)?;
     pre_tokenizers::init_pre_tokenizers(ruby, &pre_tokenizers)?;
     decoders::init_decoders(ruby, &decoders)?;
@@ -17,6 +17,7 @@ use tk::models::wordpiece::{WordPiece, WordPieceBuilder};
 use tk::models::ModelWrapper;
 use tk::{Model, Token};
 
+use super::utils::{marshal_dump, marshal_load};
 use super::{RbError, RbResult, MODELS};
 
 #[derive(DataTypeFunctions, Clone, Serialize, Deserialize)]
@@ -68,6 +69,16 @@ where
     }
 }
 
+impl RbModel {
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "Model")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "Model")
+    }
+}
+
 pub struct RbBPE {}
 
 impl RbBPE {
@@ -364,8 +375,9 @@ unsafe impl TypedData for RbModel {
     }
 
     fn data_type() -> &'static DataType {
-        static DATA_TYPE: DataType =
-            data_type_builder!(RbModel, "Tokenizers::Models::Model").build();
+        static DATA_TYPE: DataType = data_type_builder!(RbModel, "Tokenizers::Models::Model")
+            .frozen_shareable()
+            .build();
         &DATA_TYPE
     }
 
@@ -401,6 +413,8 @@ unsafe impl TypedData for RbModel {
 
 pub fn init_models(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let model = module.define_class("Model", ruby.class_object())?;
+    model.define_singleton_method("_load", function!(RbModel::load, 1))?;
+    model.define_method("_dump", method!(RbModel::dump, 1))?;
 
     let class = module.define_class("BPE", model)?;
     class.define_singleton_method("_new", function!(RbBPE::new, 3))?;
@@ -33,6 +33,14 @@ impl RbNormalizer {
             .map_err(RbError::from)?;
         Ok(normalized.get().to_owned())
     }
+
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "Normalizer")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "Normalizer")
+    }
 }
 
 impl Normalizer for RbNormalizer {
@@ -365,7 +373,9 @@ unsafe impl TypedData for RbNormalizer {
 
     fn data_type() -> &'static DataType {
         static DATA_TYPE: DataType =
-            data_type_builder!(RbNormalizer, "Tokenizers::Normalizers::Normalizer").build();
+            data_type_builder!(RbNormalizer, "Tokenizers::Normalizers::Normalizer")
+                .frozen_shareable()
+                .build();
         &DATA_TYPE
     }
 
@@ -460,6 +470,7 @@ unsafe impl TypedData for RbNormalizer {
                     NormalizerWrapper::Prepend(_) => ruby.get_inner(&PREPEND),
                     NormalizerWrapper::StripNormalizer(_) => ruby.get_inner(&STRIP),
                     NormalizerWrapper::StripAccents(_) => ruby.get_inner(&STRIP_ACCENTS),
+                    NormalizerWrapper::Sequence(_) => ruby.get_inner(&SEQUENCE),
                     _ => todo!(),
                 },
             },
@@ -470,6 +481,8 @@ unsafe impl TypedData for RbNormalizer {
 pub fn init_normalizers(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let normalizer = module.define_class("Normalizer", ruby.class_object())?;
     normalizer.define_method("normalize_str", method!(RbNormalizer::normalize_str, 1))?;
+    normalizer.define_singleton_method("_load", function!(RbNormalizer::load, 1))?;
+    normalizer.define_method("_dump", method!(RbNormalizer::dump, 1))?;
 
     let class = module.define_class("Sequence", normalizer)?;
     class.define_singleton_method("new", function!(RbSequence::new, 1))?;
@@ -0,0 +1,199 @@
+use magnus::prelude::*;
+use magnus::{Error, RString, Ruby, Value};
+use tk::Encoding;
+
+use super::utils::symbol_or_string;
+use super::RbResult;
+
+#[derive(Clone, Copy)]
+pub enum DType {
+    Int32,
+    Int64,
+    Uint16,
+}
+
+impl DType {
+    pub fn new(ruby: &Ruby, value: Value) -> RbResult<Self> {
+        let dtype = symbol_or_string(value)?;
+        match dtype.as_str() {
+            "int32" => Ok(Self::Int32),
+            "int64" => Ok(Self::Int64),
+            "uint16" => Ok(Self::Uint16),
+            _ => Err(Error::new(
+                ruby.exception_arg_error(),
+                "The dtype value must be 'int32', 'int64', or 'uint16'",
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int32 => "int32",
+            Self::Int64 => "int64",
+            Self::Uint16 => "uint16",
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Self::Int32 => 4,
+            Self::Int64 => 8,
+            Self::Uint16 => 2,
+        }
+    }
+
+    /// Appends `value` in native byte order.
+    pub fn push(self, buffer: &mut Vec<u8>, value: u32) -> tk::Result<()> {
+        match self {
+            Self::Int32 => {
+                let value = i32::try_from(value)
+                    .map_err(|_| format!("Value {} is out of range for int32", value))?;
+                buffer.extend_from_slice(&value.to_ne_bytes());
+            }
+            Self::Int64 => buffer.extend_from_slice(&i64::from(value).to_ne_bytes()),
+            Self::Uint16 => {
+                let value = u16::try_from(value)
+                    .map_err(|_| format!("Value {} is out of range for uint16", value))?;
+                buffer.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A matrix that `Tokenizer#encode_for_model` can return, named like the
+/// inputs of Transformers models.
+#[derive(Clone, Copy)]
+pub enum ModelInput {
+    InputIds,
+    AttentionMask,
+    TokenTypeIds,
+    PositionIds,
+    SpecialTokensMask,
+}
+
+impl ModelInput {
+    pub fn new(ruby: &Ruby, value: Value) -> RbResult<Self> {
+        let name = symbol_or_string(value)?;
+        match name.as_str() {
+            "input_ids" | "ids" => Ok(Self::InputIds),
+            "attention_mask" => Ok(Self::AttentionMask),
+            "token_type_ids" | "type_ids" => Ok(Self::TokenTypeIds),
+            "position_ids" => Ok(Self::PositionIds),
+            "special_tokens_mask" => Ok(Self::SpecialTokensMask),
+            _ => Err(Error::new(
+                ruby.exception_arg_error(),
+                format!("Unknown model input: {}", name),
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::InputIds => "input_ids",
+            Self::AttentionMask => "attention_mask",
+            Self::TokenTypeIds => "token_type_ids",
+            Self::PositionIds => "position_ids",
+            Self::SpecialTokensMask => "special_tokens_mask",
+        }
+    }
+
+    pub fn row(self, encoding: &Encoding) -> Vec<u32> {
+        match self {
+            Self::InputIds => encoding.get_ids().to_vec(),
+            Self::AttentionMask => encoding.get_attention_mask().to_vec(),
+            Self::TokenTypeIds => encoding.get_type_ids().to_vec(),
+            Self::PositionIds => {
+                // count only attended tokens so left padding doesn't shift
+                // positions, and use 1 for padding like Transformers does
+                let mut position = 0;
+                encoding
+                    .get_attention_mask()
+                    .iter()
+                    .map(|&mask| {
+                        if mask == 0 {
+                            1
+                        } else {
+                            position += 1;
+                            position - 1
+                        }
+                    })
+                    .collect()
+            }
+            Self::SpecialTokensMask => encoding.get_special_tokens_mask().to_vec(),
+        }
+    }
+}
+
+/// A `[batch, seq]` matrix of ids and masks, packed into flat buffers so no
+/// Ruby object is created per token. Built without the GVL.
+pub struct PackedBatch {
+    dtype: DType,
+    shape: (usize, usize),
+    ids: Vec<u8>,
+    attention_mask: Vec<u8>,
+    type_ids: Vec<u8>,
+    special_tokens_mask: Vec<u8>,
+}
+
+impl PackedBatch {
+    /// Packs encodings that all have the same length.
+    pub fn new(encodings: &[Encoding], dtype: DType) -> tk::Result<Self> {
+        let seq_len = encodings.first().map_or(0, |e| e.len());
+        if encodings.iter().any(|e| e.len() != seq_len) {
+            return Err("Encodings must all have the same length to be packed".into());
+        }
+
+        let capacity = encodings.len() * seq_len * dtype.size();
+        let mut batch = Self {
+            dtype,
+            shape: (encodings.len(), seq_len),
+            ids: Vec::with_capacity(capacity),
+            attention_mask: Vec::with_capacity(capacity),
+            type_ids: Vec::with_capacity(capacity),
+            special_tokens_mask: Vec::with_capacity(capacity),
+        };
+        for encoding in encodings {
+            for &v in encoding.get_ids() {
+                dtype.push(&mut batch.ids, v)?;
+            }
+            for &v in encoding.get_attention_mask() {
+                dtype.push(&mut batch.attention_mask, v)?;
+            }
+            for &v in encoding.get_type_ids() {
+                dtype.push(&mut batch.type_ids, v)?;
+            }
+            for &v in encoding.get_special_tokens_mask() {
+                dtype.push(&mut batch.special_tokens_mask, v)?;
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Arguments for `Tokenizers::PackedBatch.new`. Each buffer is copied into
+    /// a frozen string once, which the batch hands out as is.
+    pub fn into_args(self, ruby: &Ruby) -> PackedBatchArgs {
+        let string = |buffer: Vec<u8>| {
+            let string = ruby.str_from_slice(&buffer);
+            string.freeze();
+            string
+        };
+        (
+            self.dtype.name(),
+            self.shape,
+            string(self.ids),
+            string(self.attention_mask),
+            string(self.type_ids),
+            string(self.special_tokens_mask),
+        )
+    }
+}
+
+pub type PackedBatchArgs = (
+    &'static str,
+    (usize, usize),
+    RString,
+    RString,
+    RString,
+    RString,
+);
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use magnus::{function, Error, Module, Object, RModule, Ruby};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use super::RbResult;
+
+/// Thread count set with `Tokenizers.num_threads=`, or 0 for the global pool.
+static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Process that first used Rayon's global pool. Forked children don't
+/// inherit worker threads, so they must never wait on pools from the parent.
+static GLOBAL_POOL_PID: AtomicUsize = AtomicUsize::new(0);
+
+static POOLS: Mutex<Option<Pools>> = Mutex::new(None);
+
+struct Pools {
+    pid: u32,
+    pools: HashMap<usize, Arc<ThreadPool>>,
+}
+
+fn pool(num_threads: usize) -> tk::Result<Arc<ThreadPool>> {
+    let pid = std::process::id();
+    let mut pools = POOLS.lock().unwrap();
+    if matches!(pools.as_ref(), Some(p) if p.pid != pid) {
+        // the workers of pools from before a fork don't exist in this
+        // process, so leak the pools instead of signaling them on drop
+        std::mem::forget(pools.take());
+    }
+    let pools = pools.get_or_insert_with(|| Pools {
+        pid,
+        pools: HashMap::new(),
+    });
+    if let Some(pool) = pools.pools.get(&num_threads) {
+        return Ok(pool.clone());
+    }
+    let pool = Arc::new(
+        ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("tokenizers-{}", i))
+            .build()?,
+    );
+    pools.pools.insert(num_threads, pool.clone());
+    Ok(pool)
+}
+
+fn forked() -> bool {
+    let pid = GLOBAL_POOL_PID.load(Ordering::SeqCst);
+    pid != 0 && pid != std::process::id() as usize
+}
+
+/// Records this process as the one using Rayon's global pool, which starts
+/// its workers the first time it's touched.
+fn use_global_pool() {
+    GLOBAL_POOL_PID
+        .compare_exchange(
+            0,
+            std::process::id() as usize,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .ok();
+}
+
+/// Runs `op` on the pool for `threads`, falling back to `Tokenizers.num_threads`
+/// and then to Rayon's global pool. After a fork, a dedicated pool is used
+/// instead of the global one, whose workers only exist in the parent.
+pub fn install<T, F>(threads: Option<usize>, op: F) -> tk::Result<T>
+where
+    F: FnOnce() -> tk::Result<T> + Send,
+    T: Send,
+{
+    let num_threads = match threads.unwrap_or_else(|| NUM_THREADS.load(Ordering::SeqCst)) {
+        0 if forked() => rayon::current_num_threads(),
+        0 => {
+            use_global_pool();
+            return op();
+        }
+        n => n,
+    };
+    pool(num_threads)?.install(op)
+}
+
+pub fn check_threads(ruby: &Ruby, threads: Option<usize>) -> RbResult<Option<usize>> {
+    match threads {
+        Some(0) => Err(Error::new(
+            ruby.exception_arg_error(),
+            "threads must be greater than 0",
+        )),
+        threads => Ok(threads),
+    }
+}
+
+fn parallelism() -> bool {
+    tk::parallelism::get_parallelism()
+}
+
+fn set_parallelism(value: bool) {
+    tk::parallelism::set_parallelism(value);
+}
+
+fn num_threads() -> usize {
+    match NUM_THREADS.load(Ordering::SeqCst) {
+        0 => {
+            if !forked() {
+                use_global_pool();
+            }
+            rayon::current_num_threads()
+        }
+        n => n,
+    }
+}
+
+fn set_num_threads(ruby: &Ruby, value: Option<usize>) -> RbResult<()> {
+    let value = check_threads(ruby, value)?;
+    NUM_THREADS.store(value.unwrap_or(0), Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn init_parallelism(module: &RModule) -> RbResult<()> {
+    module.define_singleton_method("parallelism", function!(parallelism, 0))?;
+    module.define_singleton_method("parallelism=", function!(set_parallelism, 1))?;
+    module.define_singleton_method("num_threads", function!(num_threads, 0))?;
+    module.define_singleton_method("num_threads=", function!(set_num_threads, 1))?;
+    Ok(())
+}
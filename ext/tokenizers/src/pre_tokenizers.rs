@@ -44,6 +44,14 @@ impl RbPreTokenizer {
             .map(|(s, o, _)| (s.to_owned(), o))
             .collect())
     }
+
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "PreTokenizer")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "PreTokenizer")
+    }
 }
 
 macro_rules! getter {
@@ -402,7 +410,9 @@ unsafe impl TypedData for RbPreTokenizer {
 
     fn data_type() -> &'static DataType {
         static DATA_TYPE: DataType =
-            data_type_builder!(RbPreTokenizer, "Tokenizers::PreTokenizers::PreTokenizer").build();
+            data_type_builder!(RbPreTokenizer, "Tokenizers::PreTokenizers::PreTokenizer")
+                .frozen_shareable()
+                .build();
         &DATA_TYPE
     }
 
@@ -503,6 +513,7 @@ unsafe impl TypedData for RbPreTokenizer {
                     PreTokenizerWrapper::UnicodeScripts(_) => ruby.get_inner(&UNICODE_SCRIPTS),
                     PreTokenizerWrapper::Whitespace(_) => ruby.get_inner(&WHITESPACE),
                     PreTokenizerWrapper::WhitespaceSplit(_) => ruby.get_inner(&WHITESPACE_SPLIT),
+                    PreTokenizerWrapper::Sequence(_) => ruby.get_inner(&SEQUENCE),
                     _ => todo!(),
                 },
             },
@@ -516,6 +527,8 @@ pub fn init_pre_tokenizers(ruby: &Ruby, module: &RModule) -> RbResult<()> {
         "pre_tokenize_str",
         method!(RbPreTokenizer::pre_tokenize_str, 1),
     )?;
+    pre_tokenizer.define_singleton_method("_load", function!(RbPreTokenizer::load, 1))?;
+    pre_tokenizer.define_method("_dump", method!(RbPreTokenizer::dump, 1))?;
 
     let class = module.define_class("Sequence", pre_tokenizer)?;
     class.define_singleton_method("new", function!(RbSequence::new, 1))?;
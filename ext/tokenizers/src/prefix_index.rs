@@ -0,0 +1,115 @@
+//! Lookup of tokens by the bytes they decode to. Tokens are sorted by their
+//! bytes, so the tokens starting with some bytes are next to each other.
+
+use std::sync::Arc;
+
+use magnus::{method, RModule, RString, Ruby};
+
+use super::RbResult;
+
+#[derive(Clone)]
+#[magnus::wrap(class = "Tokenizers::PrefixIndex", frozen_shareable)]
+pub struct RbPrefixIndex {
+    /// Shared, so tokenizers can keep an index and hand out copies.
+    tokens: Arc<Vec<(Vec<u8>, u32)>>,
+}
+
+impl RbPrefixIndex {
+    /// Builds the index from the decoded bytes of each token, indexed by id.
+    /// Tokens without bytes are left out.
+    pub fn new(vocab: Vec<Option<Vec<u8>>>) -> Self {
+        let mut tokens: Vec<(Vec<u8>, u32)> = vocab
+            .into_iter()
+            .zip(0..)
+            .filter_map(|(bytes, id)| bytes.filter(|b| !b.is_empty()).map(|b| (b, id)))
+            .collect();
+        tokens.sort_unstable();
+        Self {
+            tokens: Arc::new(tokens),
+        }
+    }
+
+    /// Ids of the tokens whose bytes start with `prefix`.
+    pub fn with_prefix(&self, prefix: &[u8]) -> Vec<u32> {
+        let start = self
+            .tokens
+            .partition_point(|(bytes, _)| bytes.as_slice() < prefix);
+        let mut ids: Vec<u32> = self.tokens[start..]
+            .iter()
+            .take_while(|(bytes, _)| bytes.starts_with(prefix))
+            .map(|&(_, id)| id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Whether a token has more bytes after `prefix`.
+    pub fn is_extended(&self, prefix: &[u8]) -> bool {
+        let start = self
+            .tokens
+            .partition_point(|(bytes, _)| bytes.as_slice() < prefix);
+        self.tokens[start..]
+            .iter()
+            .take_while(|(bytes, _)| bytes.starts_with(prefix))
+            .any(|(bytes, _)| bytes.len() > prefix.len())
+    }
+
+    /// Ids of the tokens whose bytes are a prefix of `text`, with their length.
+    pub fn prefixes_of(&self, text: &[u8]) -> Vec<(u32, usize)> {
+        let mut ids = Vec::new();
+        for len in 1..=text.len() {
+            let prefix = &text[..len];
+            let start = self
+                .tokens
+                .partition_point(|(bytes, _)| bytes.as_slice() < prefix);
+            ids.extend(
+                self.tokens[start..]
+                    .iter()
+                    .take_while(|(bytes, _)| bytes == prefix)
+                    .map(|&(_, id)| (id, len)),
+            );
+        }
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Ids of all tokens.
+    pub fn ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.tokens.iter().map(|&(_, id)| id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn tokens_with_prefix(&self, prefix: RString) -> Vec<u32> {
+        // copy, since the slice is only valid until Ruby runs again
+        let prefix = unsafe { prefix.as_slice() }.to_vec();
+        self.with_prefix(&prefix)
+    }
+
+    pub fn prefix_tokens_of(&self, text: RString) -> Vec<u32> {
+        let text = unsafe { text.as_slice() }.to_vec();
+        self.prefixes_of(&text)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tokens.len()
+    }
+}
+
+pub fn init_prefix_index(ruby: &Ruby, module: &RModule) -> RbResult<()> {
+    let class = module.define_class("PrefixIndex", ruby.class_object())?;
+    class.define_method(
+        "tokens_with_prefix",
+        method!(RbPrefixIndex::tokens_with_prefix, 1),
+    )?;
+    class.define_method(
+        "prefix_tokens_of",
+        method!(RbPrefixIndex::prefix_tokens_of, 1),
+    )?;
+    class.define_method("size", method!(RbPrefixIndex::size, 0))?;
+
+    Ok(())
+}
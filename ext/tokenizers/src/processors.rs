@@ -2,8 +2,8 @@ use std::sync::Arc;
 use std::sync::RwLock;
 
 use magnus::{
-    data_type_builder, function, value::Lazy, Class, DataType, DataTypeFunctions, Module, Object,
-    RArray, RClass, RModule, Ruby, TryConvert, TypedData, Value,
+    data_type_builder, function, method, value::Lazy, Class, DataType, DataTypeFunctions, Module,
+    Object, RArray, RClass, RModule, Ruby, TryConvert, TypedData, Value,
 };
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -14,7 +14,8 @@ use tk::processors::template::{SpecialToken, Template};
 use tk::processors::PostProcessorWrapper;
 use tk::{Encoding, PostProcessor};
 
-use super::{RbResult, PROCESSORS};
+use super::utils::{marshal_dump, marshal_load};
+use super::{RbError, RbResult, PROCESSORS};
 
 #[derive(DataTypeFunctions, Clone, Deserialize, Serialize)]
 #[serde(transparent)]
@@ -26,6 +27,14 @@ impl RbPostProcessor {
     pub fn new(processor: RbPostProcessorTypeWrapper) -> Self {
         RbPostProcessor { processor }
     }
+
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "PostProcessor")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "PostProcessor")
+    }
 }
 
 impl<I> From<I> for RbPostProcessor
@@ -283,7 +292,9 @@ unsafe impl TypedData for RbPostProcessor {
 
     fn data_type() -> &'static DataType {
         static DATA_TYPE: DataType =
-            data_type_builder!(RbPostProcessor, "Tokenizers::Processors::PostProcessor").build();
+            data_type_builder!(RbPostProcessor, "Tokenizers::Processors::PostProcessor")
+                .frozen_shareable()
+                .build();
         &DATA_TYPE
     }
 
@@ -337,6 +348,8 @@ unsafe impl TypedData for RbPostProcessor {
 
 pub fn init_processors(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let post_processor = module.define_class("PostProcessor", ruby.class_object())?;
+    post_processor.define_singleton_method("_load", function!(RbPostProcessor::load, 1))?;
+    post_processor.define_method("_dump", method!(RbPostProcessor::dump, 1))?;
 
     let class = module.define_class("BertProcessing", post_processor)?;
     class.define_singleton_method("new", function!(RbBertProcessing::new, 2))?;
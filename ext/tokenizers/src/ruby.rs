@@ -1,24 +1,30 @@
 use std::ffi::c_void;
+use std::fmt;
 use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use magnus::Ruby;
+use magnus::{Error, Ruby};
 use rb_sys::rb_thread_call_without_gvl;
 
+use super::{RbError, RbResult};
+
 pub trait GvlExt {
-    fn detach<T, F>(&self, func: F) -> T
+    fn detach<T, F>(&self, func: F) -> RbResult<T>
     where
-        F: Send + FnOnce() -> T,
+        F: Send + FnOnce(&Interrupt) -> tk::Result<T>,
         T: Send;
 }
 
 impl GvlExt for Ruby {
-    fn detach<T, F>(&self, func: F) -> T
+    fn detach<T, F>(&self, func: F) -> RbResult<T>
     where
-        F: Send + FnOnce() -> T,
+        F: Send + FnOnce(&Interrupt) -> tk::Result<T>,
         T: Send,
     {
+        let interrupt = Interrupt::default();
         let mut data = CallbackData {
             func: Some(func),
+            interrupt: &interrupt,
             result: None,
         };
 
@@ -26,26 +32,70 @@ impl GvlExt for Ruby {
             rb_thread_call_without_gvl(
                 Some(call_without_gvl::<F, T>),
                 &mut data as *mut _ as *mut c_void,
-                None,
-                null_mut(),
+                Some(unblock),
+                &interrupt as *const _ as *mut c_void,
             );
         }
 
-        data.result.unwrap()
+        match data.result.unwrap() {
+            Err(e) if e.is::<Interrupted>() => {
+                // raise the pending exception (Interrupt, Thread#kill, Timeout)
+                self.thread_check_ints()?;
+                Err(Error::new(self.exception_interrupt(), e.to_string()))
+            }
+            result => result.map_err(RbError::from),
+        }
+    }
+}
+
+/// Cancellation flag for work running without the GVL. It is set by the
+/// unblocking function when Ruby interrupts the thread, and checked by the
+/// work between items.
+#[derive(Default)]
+pub struct Interrupt(AtomicBool);
+
+impl Interrupt {
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn check(&self) -> tk::Result<()> {
+        if self.0.load(Ordering::Relaxed) {
+            Err(Box::new(Interrupted))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interrupted")
     }
 }
 
-struct CallbackData<F, T> {
+impl std::error::Error for Interrupted {}
+
+struct CallbackData<'a, F, T> {
     func: Option<F>,
-    result: Option<T>,
+    interrupt: &'a Interrupt,
+    result: Option<tk::Result<T>>,
 }
 
 extern "C" fn call_without_gvl<F, T>(data: *mut c_void) -> *mut c_void
 where
-    F: FnOnce() -> T,
+    F: FnOnce(&Interrupt) -> tk::Result<T>,
 {
     let data = unsafe { &mut *(data as *mut CallbackData<F, T>) };
     let func = data.func.take().unwrap();
-    data.result = Some(func());
+    data.result = Some(func(data.interrupt));
     null_mut()
 }
+
+extern "C" fn unblock(data: *mut c_void) {
+    let interrupt = unsafe { &*(data as *const Interrupt) };
+    interrupt.set();
+}
@@ -0,0 +1,169 @@
+//! Detection of stop sequences in streamed tokens. Text is matched on the
+//! decoded bytes, so stop sequences can span tokens, and text that could be
+//! the start of a stop sequence is held back until it's known not to be.
+
+use std::sync::{Mutex, MutexGuard};
+
+use magnus::{method, Error, RHash, RModule, Ruby};
+
+use super::tokenizer::RbTokenizer;
+use super::{RbError, RbResult};
+
+struct Matcher {
+    stops: Vec<Vec<u8>>,
+    pending: Vec<u8>,
+    stopped: bool,
+    /// Whether a token was decoded, after which tokens don't start the text.
+    started: bool,
+}
+
+impl Matcher {
+    /// Adds the bytes of a token, and returns the text that can be emitted
+    /// along with the index of the stop sequence that was found, if any.
+    fn push(&mut self, bytes: &[u8]) -> (String, Option<usize>) {
+        self.pending.extend_from_slice(bytes);
+
+        // the earliest stop sequence wins
+        let found = self
+            .stops
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stop)| find(&self.pending, stop).map(|start| (start, index)))
+            .min();
+        if let Some((start, index)) = found {
+            let text = String::from_utf8_lossy(&self.pending[..start]).into_owned();
+            self.pending.clear();
+            self.stopped = true;
+            return (text, Some(index));
+        }
+
+        let held = self
+            .stops
+            .iter()
+            .map(|stop| partial_match(&self.pending, stop))
+            .max()
+            .unwrap_or(0);
+        let end = char_boundary(&self.pending[..self.pending.len() - held]);
+        let text = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+        self.pending.drain(..end);
+        (text, None)
+    }
+
+    fn flush(&mut self) -> String {
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        text
+    }
+}
+
+fn find(bytes: &[u8], stop: &[u8]) -> Option<usize> {
+    bytes.windows(stop.len()).position(|window| window == stop)
+}
+
+/// Length of the longest end of `bytes` that's the start of `stop`.
+fn partial_match(bytes: &[u8], stop: &[u8]) -> usize {
+    (1..stop.len().min(bytes.len() + 1))
+        .rev()
+        .find(|&len| bytes.ends_with(&stop[..len]))
+        .unwrap_or(0)
+}
+
+/// End of the complete characters of `bytes`, so a character split between
+/// tokens is emitted once all of its bytes are there.
+fn char_boundary(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    }
+}
+
+#[magnus::wrap(class = "Tokenizers::StopSequenceMatcher")]
+pub struct RbStopSequenceMatcher {
+    tokenizer: RbTokenizer,
+    stops: Vec<String>,
+    matcher: Mutex<Matcher>,
+}
+
+impl RbStopSequenceMatcher {
+    pub fn new(ruby: &Ruby, tokenizer: RbTokenizer, stops: Vec<String>) -> RbResult<Self> {
+        if stops.iter().any(|stop| stop.is_empty()) {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "stop sequences can't be empty",
+            ));
+        }
+        let matcher = Matcher {
+            stops: stops.iter().map(|stop| stop.as_bytes().to_vec()).collect(),
+            pending: Vec::new(),
+            stopped: false,
+            started: false,
+        };
+        Ok(Self {
+            tokenizer,
+            stops,
+            matcher: Mutex::new(matcher),
+        })
+    }
+
+    fn matcher(&self) -> RbResult<MutexGuard<'_, Matcher>> {
+        self.matcher
+            .lock()
+            .map_err(|_| RbError::new_err("StopSequenceMatcher Mutex is poisoned"))
+    }
+
+    /// Text matches `decode`, so special tokens are skipped unless they're
+    /// stop sequences, and the first token is decoded as the start of a text.
+    pub fn push(ruby: &Ruby, rb_self: &Self, id: u32) -> RbResult<RHash> {
+        let mut matcher = rb_self.matcher()?;
+        if matcher.stopped {
+            return Err(RbError::new_err("Stop sequence already found"));
+        }
+        let bytes = rb_self
+            .tokenizer
+            .decoded_token(id, !matcher.started)?
+            .ok_or_else(|| Error::new(ruby.exception_arg_error(), format!("Unknown id: {}", id)))?;
+        let skipped = match rb_self.tokenizer.special_token(id)? {
+            Some(token) => !rb_self.stops.contains(&token),
+            None => false,
+        };
+        let (text, stop) = if skipped {
+            (String::new(), None)
+        } else {
+            matcher.started = true;
+            matcher.push(&bytes)
+        };
+
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("text"), text)?;
+        hash.aset(
+            ruby.to_symbol("stop"),
+            stop.map(|index| rb_self.stops[index].as_str()),
+        )?;
+        Ok(hash)
+    }
+
+    pub fn flush(&self) -> RbResult<String> {
+        Ok(self.matcher()?.flush())
+    }
+
+    pub fn is_stopped(&self) -> RbResult<bool> {
+        Ok(self.matcher()?.stopped)
+    }
+
+    pub fn stop_sequences(&self) -> Vec<String> {
+        self.stops.clone()
+    }
+}
+
+pub fn init_stop_sequences(ruby: &Ruby, module: &RModule) -> RbResult<()> {
+    let class = module.define_class("StopSequenceMatcher", ruby.class_object())?;
+    class.define_method("push", method!(RbStopSequenceMatcher::push, 1))?;
+    class.define_method("flush", method!(RbStopSequenceMatcher::flush, 0))?;
+    class.define_method("stopped?", method!(RbStopSequenceMatcher::is_stopped, 0))?;
+    class.define_method(
+        "stop_sequences",
+        method!(RbStopSequenceMatcher::stop_sequences, 0),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Helpers that slice the original text using the byte offsets of an
+//! encoding, so results are exact substrings of the input.
+
+use tk::Offsets;
+
+#[derive(Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+    Middle,
+}
+
+/// End of the text kept before token `index`. Tokens can share a character
+/// (byte-level models split multi-byte characters), so the end is clamped to
+/// the start of the next token to never keep part of a dropped token.
+fn end_before(offsets: &[Offsets], index: usize) -> usize {
+    match index {
+        0 => 0,
+        i if i < offsets.len() => offsets[i - 1].1.min(offsets[i].0),
+        i => offsets[i - 1].1,
+    }
+}
+
+/// Start of the text kept from token `index` on. See `end_before`.
+fn start_from(text: &str, offsets: &[Offsets], index: usize) -> usize {
+    match index {
+        i if i >= offsets.len() => text.len(),
+        0 => offsets[0].0,
+        i => offsets[i].0.max(offsets[i - 1].1),
+    }
+}
+
+/// Moves `index` back to the nearest char boundary.
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Moves `index` forward to the nearest char boundary.
+fn ceil_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Text before token `index`, including any leading text.
+pub fn head<'a>(text: &'a str, offsets: &[Offsets], index: usize) -> &'a str {
+    &text[..floor_boundary(text, end_before(offsets, index))]
+}
+
+/// Text from token `index` on, including any trailing text.
+pub fn tail<'a>(text: &'a str, offsets: &[Offsets], index: usize) -> &'a str {
+    &text[ceil_boundary(text, start_from(text, offsets, index))..]
+}
+
+/// Text from token `start` up to token `end`.
+pub fn slice<'a>(text: &'a str, offsets: &[Offsets], start: usize, end: usize) -> (usize, &'a str) {
+    let from = ceil_boundary(text, start_from(text, offsets, start));
+    let to = floor_boundary(text, end_before(offsets, end)).max(from);
+    (from, &text[from..to])
+}
+
+/// Keeps at most `max_tokens` tokens of `text`. `Middle` keeps the start and
+/// the end of the text and drops tokens in between.
+pub fn truncate(text: &str, offsets: &[Offsets], max_tokens: usize, side: Side) -> String {
+    if offsets.len() <= max_tokens {
+        return text.to_string();
+    }
+    match side {
+        Side::Right => head(text, offsets, max_tokens).to_string(),
+        Side::Left => tail(text, offsets, offsets.len() - max_tokens).to_string(),
+        Side::Middle => {
+            let tail_tokens = max_tokens / 2;
+            let head_tokens = max_tokens - tail_tokens;
+            // keep the text between the head and the first dropped token, so
+            // words on both sides stay separated
+            let end = floor_boundary(text, start_from(text, offsets, head_tokens));
+            let mut result = text[..end].to_string();
+            result.push_str(tail(text, offsets, offsets.len() - tail_tokens));
+            result
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Boundary {
+    Paragraph,
+    Sentence,
+    Word,
+}
+
+impl Boundary {
+    /// Whether a chunk can end right before token `index`.
+    fn allows_break(self, text: &str, offsets: &[Offsets], index: usize) -> bool {
+        let (prev_start, prev_end) = offsets[index - 1];
+        let gap = text.get(prev_end..offsets[index].0).unwrap_or("");
+        match self {
+            Self::Paragraph => gap.matches('\n').count() >= 2,
+            Self::Sentence => {
+                let prev = text.get(prev_start..prev_end).unwrap_or("");
+                gap.contains('\n')
+                    || (!gap.is_empty() && prev.ends_with(['.', '!', '?', '。', '！', '？']))
+            }
+            Self::Word => gap.chars().any(char::is_whitespace),
+        }
+    }
+}
+
+pub struct Chunk<'a> {
+    pub text: &'a str,
+    pub start: usize,
+    pub end: usize,
+    pub num_tokens: usize,
+}
+
+/// Counts chars up to increasing byte positions without rescanning the text.
+struct CharCounter {
+    byte: usize,
+    chars: usize,
+}
+
+impl CharCounter {
+    fn at(&mut self, text: &str, byte: usize) -> usize {
+        self.chars += text[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.chars
+    }
+}
+
+/// Splits `text` into chunks of at most `max_tokens` tokens, where each chunk
+/// repeats the last `overlap` tokens of the previous one. Chunks end at the
+/// latest break allowed by the first boundary in `boundaries` that has one,
+/// or are cut at `max_tokens` otherwise. Chunk offsets are in chars.
+pub fn chunk<'a>(
+    text: &'a str,
+    offsets: &[Offsets],
+    max_tokens: usize,
+    overlap: usize,
+    boundaries: &[Boundary],
+) -> Vec<Chunk<'a>> {
+    let mut chunks = Vec::new();
+    let mut starts = CharCounter { byte: 0, chars: 0 };
+    let mut ends = CharCounter { byte: 0, chars: 0 };
+    let mut start = 0;
+    while start < offsets.len() {
+        let limit = (start + max_tokens).min(offsets.len());
+        let end = if limit == offsets.len() {
+            limit
+        } else {
+            // the next chunk must start after this one
+            let earliest = start + overlap + 1;
+            boundaries
+                .iter()
+                .find_map(|boundary| {
+                    (earliest..=limit)
+                        .rev()
+                        .find(|&i| boundary.allows_break(text, offsets, i))
+                })
+                .unwrap_or(limit)
+        };
+
+        let (from, chunk_text) = slice(text, offsets, start, end);
+        let to = from + chunk_text.len();
+        chunks.push(Chunk {
+            text: chunk_text,
+            start: starts.at(text, from),
+            end: ends.at(text, to),
+            num_tokens: end - start,
+        });
+
+        if end == offsets.len() {
+            break;
+        }
+        start = end - overlap;
+    }
+    chunks
+}
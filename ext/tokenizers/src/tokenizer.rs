@@ -1,29 +1,51 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::path::PathBuf;
+use std::slice;
 use std::str::FromStr;
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::Duration;
 
 use magnus::prelude::*;
-use magnus::{function, method, Error, RArray, RHash, RModule, RString, Ruby, TryConvert, Value};
+use magnus::typed_data::Obj;
+use magnus::{
+    function, method, Error, RArray, RHash, RModule, RString, Ruby, TryConvert, TypedData, Value,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tk::parallelism::MaybeParallelIterator;
 use tk::tokenizer::{
-    Model, PaddingDirection, PaddingParams, PaddingStrategy, TokenizerImpl, TruncationDirection,
-    TruncationParams, TruncationStrategy,
+    pad_encodings, Model, PaddingDirection, PaddingParams, PaddingStrategy, TokenizerImpl,
+    TruncationDirection, TruncationParams, TruncationStrategy,
 };
-use tk::AddedToken;
+use tk::utils::truncation::truncate_encodings;
+use tk::{AddedToken, Encoding, NormalizedString, Normalizer};
 
 use crate::tk::PostProcessor;
 
+use super::constraint::RbConstraint;
 use super::decoders::RbDecoder;
 use super::encoding::RbEncoding;
+use super::fields::{self, FieldTemplate};
 use super::models::RbModel;
 use super::normalizers::RbNormalizer;
+use super::packed::{DType, ModelInput, PackedBatch, PackedBatchArgs};
+use super::parallelism;
 use super::pre_tokenizers::RbPreTokenizer;
+use super::prefix_index::RbPrefixIndex;
 use super::processors::RbPostProcessor;
-use super::ruby::GvlExt;
-use super::trainers::RbTrainer;
+use super::ruby::{GvlExt, Interrupt};
+use super::stop_sequences::RbStopSequenceMatcher;
+use super::text::{self, Boundary, Side};
+use super::trainers::{ObservedTrainer, Progress, ProgressEvent, RbTrainer};
+use super::truncation::{select_as, MiddleTruncation};
+use super::utils::{marshal_dump, marshal_load, symbol_or_string};
 use super::{RbError, RbResult};
 
-#[magnus::wrap(class = "Tokenizers::AddedToken")]
+#[magnus::wrap(class = "Tokenizers::AddedToken", frozen_shareable)]
 pub struct RbAddedToken {
     pub content: String,
     pub special: bool,
@@ -115,6 +137,14 @@ impl RbAddedToken {
         Ok(token)
     }
 
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(&self.get_token(), "AddedToken")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load::<tk::AddedToken>(&data, "AddedToken").map(|token| token.into())
+    }
+
     pub fn get_content(&self) -> String {
         self.content.to_string()
     }
@@ -256,15 +286,730 @@ impl<'s> From<PreTokenizedEncodeInput<'s>> for tk::tokenizer::EncodeInput<'s> {
 
 type Tokenizer = TokenizerImpl<RbModel, RbNormalizer, RbPreTokenizer, RbPostProcessor, RbDecoder>;
 
-#[magnus::wrap(class = "Tokenizers::Tokenizer")]
+fn batch_inputs(input: RArray, is_pretokenized: bool) -> RbResult<Vec<tk::EncodeInput<'static>>> {
+    input
+        .into_iter()
+        .map(|o| {
+            let input: tk::EncodeInput = if is_pretokenized {
+                PreTokenizedEncodeInput::try_convert(o)?.into()
+            } else {
+                TextEncodeInput::try_convert(o)?.into()
+            };
+            Ok(input)
+        })
+        .collect()
+}
+
+/// Same as `TokenizerImpl::encode_batch`, but stops between inputs once the
+/// calling Ruby thread is interrupted.
+fn encode_batch_interruptible<'s, F>(
+    tokenizer: &Tokenizer,
+    encoder: &Encoder,
+    inputs: Vec<tk::EncodeInput<'s>>,
+    interrupt: &Interrupt,
+    add_special_tokens: bool,
+    encode: F,
+) -> tk::Result<Vec<RbEncoding>>
+where
+    F: Fn(&Tokenizer, tk::EncodeInput<'s>, bool) -> tk::Result<Encoding> + Sync,
+{
+    let mut results = inputs
+        .into_maybe_par_iter()
+        .map(|input| {
+            interrupt.check()?;
+            encoder.encode(tokenizer, input, add_special_tokens, &encode)
+        })
+        .collect::<tk::Result<Vec<RbEncoding>>>()?;
+
+    if let Some(params) = encoder.get_padding() {
+        let mut encodings: Vec<Encoding> = results
+            .iter_mut()
+            .map(|result| mem::take(&mut result.encoding))
+            .collect();
+        pad_encodings(&mut encodings, params)?;
+        for (result, encoding) in results.iter_mut().zip(encodings) {
+            result.encoding = encoding;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Truncation parameters, where `Middle` is handled by the bindings since
+/// Tokenizers has no such strategy.
+#[derive(Clone)]
+enum Truncation {
+    Params(TruncationParams),
+    Middle(MiddleTruncation),
+}
+
+impl Truncation {
+    /// Sets the truncation of `tokenizer`, returning the middle truncation to
+    /// apply on top of it.
+    fn set(
+        truncation: Option<&Self>,
+        tokenizer: &mut Tokenizer,
+    ) -> tk::Result<Option<MiddleTruncation>> {
+        match truncation {
+            Some(Self::Params(params)) => {
+                tokenizer.with_truncation(Some(params.clone()))?;
+                Ok(None)
+            }
+            Some(Self::Middle(middle)) => {
+                // validate the stride the same way as other strategies
+                tokenizer.with_truncation(Some(TruncationParams {
+                    max_length: middle.max_length,
+                    stride: middle.stride,
+                    ..Default::default()
+                }))?;
+                tokenizer.with_truncation(None)?;
+                Ok(Some(*middle))
+            }
+            None => {
+                tokenizer.with_truncation(None)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Checks the stride the same way as `TokenizerImpl::with_truncation`,
+    /// for truncation applied to encodings instead of set on `tokenizer`.
+    fn check(&self, tokenizer: &Tokenizer) -> tk::Result<()> {
+        let (max_length, stride) = match self {
+            Self::Params(params) => (params.max_length, params.stride),
+            Self::Middle(middle) => (middle.max_length, middle.stride),
+        };
+        let added_tokens = tokenizer
+            .get_post_processor()
+            .map_or(0, |processor| processor.added_tokens(false));
+        let effective_max_length = max_length.saturating_sub(added_tokens);
+        if effective_max_length < stride {
+            return Err(format!(
+                "tokenizer stride set to {}, which is greater than or equal to its effective max length of {} (= {} original max length - {} added special tokens)",
+                stride, effective_max_length, max_length, added_tokens
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn truncation_params(ruby: &Ruby, max_length: usize, kwargs: RHash) -> RbResult<Truncation> {
+    let mut params = TruncationParams {
+        max_length,
+        ..Default::default()
+    };
+    let mut middle = false;
+
+    let value: Value = kwargs.delete(ruby.to_symbol("stride"))?;
+    if !value.is_nil() {
+        params.stride = TryConvert::try_convert(value)?;
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("strategy"))?;
+    if !value.is_nil() {
+        let strategy_str = String::try_convert(value)?;
+        params.strategy = match strategy_str.as_str() {
+            "longest_first" => TruncationStrategy::LongestFirst,
+            "only_first" => TruncationStrategy::OnlyFirst,
+            "only_second" => TruncationStrategy::OnlySecond,
+            "middle" => {
+                middle = true;
+                TruncationStrategy::LongestFirst
+            }
+            _ => return Err(Error::new(
+                ruby.exception_arg_error(),
+                "The strategy value must be 'longest_first', 'only_first', 'only_second', or 'middle'",
+            )),
+        }
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("direction"))?;
+    if !value.is_nil() {
+        if middle {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "direction can't be used with the 'middle' strategy",
+            ));
+        }
+        let dir_str = String::try_convert(value)?;
+        params.direction = match dir_str.as_str() {
+            "left" => TruncationDirection::Left,
+            "right" => TruncationDirection::Right,
+            _ => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The direction value must be 'left' or 'right'",
+                ))
+            }
+        }
+    }
+
+    let mut head_ratio = 0.5;
+    let value: Value = kwargs.delete(ruby.to_symbol("head_ratio"))?;
+    if !value.is_nil() {
+        if !middle {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "head_ratio requires the 'middle' strategy",
+            ));
+        }
+        head_ratio = TryConvert::try_convert(value)?;
+        if !(0.0..=1.0).contains(&head_ratio) {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "head_ratio must be between 0 and 1",
+            ));
+        }
+    }
+
+    if !kwargs.is_empty() {
+        // TODO improve message
+        return Err(Error::new(ruby.exception_arg_error(), "unknown keyword"));
+    }
+
+    if middle {
+        Ok(Truncation::Middle(MiddleTruncation {
+            max_length: params.max_length,
+            stride: params.stride,
+            head_ratio,
+        }))
+    } else {
+        Ok(Truncation::Params(params))
+    }
+}
+
+// TODO support more kwargs
+fn padding_params(ruby: &Ruby, kwargs: RHash) -> RbResult<PaddingParams> {
+    let mut params = PaddingParams::default();
+
+    let value: Value = kwargs.delete(ruby.to_symbol("direction"))?;
+    if !value.is_nil() {
+        let dir_str = String::try_convert(value)?;
+        params.direction = match dir_str.as_str() {
+            "left" => PaddingDirection::Left,
+            "right" => PaddingDirection::Right,
+            _ => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The direction value must be 'left' or 'right'",
+                ))
+            }
+        }
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("pad_to_multiple_of"))?;
+    if !value.is_nil() {
+        params.pad_to_multiple_of = TryConvert::try_convert(value)?;
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("pad_id"))?;
+    if !value.is_nil() {
+        params.pad_id = TryConvert::try_convert(value)?;
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("pad_type_id"))?;
+    if !value.is_nil() {
+        params.pad_type_id = TryConvert::try_convert(value)?;
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("pad_token"))?;
+    if !value.is_nil() {
+        params.pad_token = TryConvert::try_convert(value)?;
+    }
+
+    let value: Value = kwargs.delete(ruby.to_symbol("length"))?;
+    if value.is_nil() {
+        params.strategy = PaddingStrategy::BatchLongest;
+    } else {
+        params.strategy = PaddingStrategy::Fixed(TryConvert::try_convert(value)?);
+    }
+
+    if !kwargs.is_empty() {
+        // TODO improve message
+        return Err(Error::new(ruby.exception_arg_error(), "unknown keyword"));
+    }
+
+    Ok(params)
+}
+
+/// Per-call `truncation:` and `padding:` overrides. `None` keeps the
+/// tokenizer's setting and `Some(None)` disables it for the call.
+#[derive(Default)]
+struct EncodeOptions {
+    truncation: Option<Option<Truncation>>,
+    padding: Option<Option<PaddingParams>>,
+    keep_text: bool,
+}
+
+impl EncodeOptions {
+    fn new(ruby: &Ruby, truncation: Value, padding: Value) -> RbResult<Self> {
+        let mut options = Self::default();
+
+        if !truncation.is_nil() {
+            options.truncation = Some(if !truncation.to_bool() {
+                None
+            } else if let Some(kwargs) = RHash::from_value(truncation) {
+                let kwargs: RHash = kwargs.funcall("dup", ())?;
+                let max_length: Value = kwargs.delete(ruby.to_symbol("max_length"))?;
+                if max_length.is_nil() {
+                    return Err(Error::new(
+                        ruby.exception_arg_error(),
+                        "missing keyword: :max_length",
+                    ));
+                }
+                Some(truncation_params(
+                    ruby,
+                    TryConvert::try_convert(max_length)?,
+                    kwargs,
+                )?)
+            } else {
+                let max_length = TryConvert::try_convert(truncation)?;
+                Some(truncation_params(ruby, max_length, ruby.hash_new())?)
+            });
+        }
+
+        if !padding.is_nil() {
+            options.padding = Some(if !padding.to_bool() {
+                None
+            } else if let Some(kwargs) = RHash::from_value(padding) {
+                Some(padding_params(ruby, kwargs.funcall("dup", ())?)?)
+            } else {
+                Some(padding_params(ruby, ruby.hash_new())?)
+            });
+        }
+
+        Ok(options)
+    }
+
+    /// Returns the encoder to use. Overrides are applied to the encodings,
+    /// so the tokenizer is never copied for them.
+    fn apply(
+        &self,
+        ruby: &Ruby,
+        rb_self: &RbTokenizer,
+        tokenizer: &RwLockReadGuard<'_, Tokenizer>,
+    ) -> RbResult<Encoder> {
+        let truncation = match &self.truncation {
+            Some(truncation) => {
+                if let Some(truncation) = truncation {
+                    truncation
+                        .check(tokenizer)
+                        .map_err(|e| Error::new(ruby.exception_arg_error(), e.to_string()))?;
+                }
+                truncation.clone()
+            }
+            None => match rb_self.middle_truncation()? {
+                Some(middle) => Some(Truncation::Middle(middle)),
+                None => tokenizer.get_truncation().cloned().map(Truncation::Params),
+            },
+        };
+        let (padding, overrides_padding) = match &self.padding {
+            Some(params) => (
+                params.clone(),
+                params.is_some() || tokenizer.get_padding().is_some(),
+            ),
+            None => (tokenizer.get_padding().cloned(), false),
+        };
+        let raw = if truncation.is_some() || overrides_padding {
+            Some(rb_self.untruncated(tokenizer)?)
+        } else {
+            None
+        };
+        Ok(Encoder {
+            raw,
+            truncation,
+            padding,
+            keep_text: self.keep_text,
+        })
+    }
+}
+
+/// Encodes inputs with the settings of a call.
+struct Encoder {
+    /// Untruncated copy of the tokenizer, which encodes sequences without
+    /// post-processing, to get their length before truncation and to truncate
+    /// and pad them with the settings of the call. Only needed when
+    /// truncating or when the call overrides padding.
+    raw: Option<Arc<Tokenizer>>,
+    truncation: Option<Truncation>,
+    padding: Option<PaddingParams>,
+    keep_text: bool,
+}
+
+impl Encoder {
+    /// Encodes `input` with `encode`, which is given the tokenizer to use.
+    fn encode<'s, F>(
+        &self,
+        tokenizer: &Tokenizer,
+        input: tk::EncodeInput<'s>,
+        add_special_tokens: bool,
+        encode: F,
+    ) -> tk::Result<RbEncoding>
+    where
+        F: Fn(&Tokenizer, tk::EncodeInput<'s>, bool) -> tk::Result<Encoding>,
+    {
+        let texts = if self.keep_text {
+            sequence_texts(tokenizer, &input)?
+        } else {
+            Vec::new()
+        };
+        let (encoding, original_length) =
+            self.encode_with_length(tokenizer, input, add_special_tokens, encode)?;
+        Ok(RbEncoding {
+            encoding,
+            original_length: Some(original_length),
+            texts,
+        })
+    }
+
+    /// Also returns the number of tokens before truncation.
+    fn encode_with_length<'s, F>(
+        &self,
+        tokenizer: &Tokenizer,
+        input: tk::EncodeInput<'s>,
+        add_special_tokens: bool,
+        encode: F,
+    ) -> tk::Result<(Encoding, usize)>
+    where
+        F: Fn(&Tokenizer, tk::EncodeInput<'s>, bool) -> tk::Result<Encoding>,
+    {
+        let raw: &Tokenizer = match &self.raw {
+            Some(raw) => raw,
+            None => {
+                let encoding = encode(tokenizer, input, add_special_tokens)?;
+                let length = encoding
+                    .get_attention_mask()
+                    .iter()
+                    .filter(|&&mask| mask == 1)
+                    .count();
+                return Ok((encoding, length));
+            }
+        };
+
+        // same steps as `TokenizerImpl::encode`, with the settings of the call
+        let (sequence, pair) = match input {
+            tk::EncodeInput::Single(sequence) => (sequence, None),
+            tk::EncodeInput::Dual(sequence, pair) => (sequence, Some(pair)),
+        };
+        let encoding = encode(raw, tk::EncodeInput::Single(sequence), false)?;
+        let pair = pair
+            .map(|pair| {
+                let mut pair = encode(raw, tk::EncodeInput::Single(pair), false)?;
+                pair.set_type_ids(vec![1; pair.len()]);
+                Ok(pair)
+            })
+            .transpose()?;
+        let added_tokens = match tokenizer.get_post_processor() {
+            Some(processor) if add_special_tokens => processor.added_tokens(pair.is_some()),
+            _ => 0,
+        };
+        let length = encoding.len() + pair.as_ref().map_or(0, |p| p.len()) + added_tokens;
+        let (encoding, pair) = match &self.truncation {
+            Some(Truncation::Params(params)) => {
+                let params = TruncationParams {
+                    max_length: params.max_length.saturating_sub(added_tokens),
+                    ..*params
+                };
+                truncate_encodings(encoding, pair, &params)?
+            }
+            Some(Truncation::Middle(middle)) => middle.truncate(encoding, pair, added_tokens),
+            None => (encoding, pair),
+        };
+        let mut encoding = process(tokenizer, encoding, pair, add_special_tokens)?;
+        if let Some(params) = &self.padding {
+            pad_encodings(slice::from_mut(&mut encoding), params)?;
+        }
+        Ok((encoding, length))
+    }
+
+    fn get_padding(&self) -> Option<&PaddingParams> {
+        self.padding.as_ref()
+    }
+}
+
+/// `word` with its first character in upper or lower case.
+fn with_first_case(word: &str, upper: bool) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) if upper => first.to_uppercase().chain(chars).collect(),
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Bytes of `token` in decoded text, after other tokens unless `start`.
+/// Tokens are kept as is without a decoder.
+fn token_bytes(tokenizer: &Tokenizer, token: &str, start: bool) -> tk::Result<Vec<u8>> {
+    match tokenizer.get_decoder() {
+        Some(decoder) => decoder.token_bytes(token, start),
+        None => Ok(token.as_bytes().to_vec()),
+    }
+}
+
+/// Bytes of each token in decoded text, indexed by id. Special tokens have
+/// none with `skip_special_tokens`, like in `decode`. Run without the GVL,
+/// since it goes through the whole vocab.
+fn decoded_vocab(
+    tokenizer: &Tokenizer,
+    interrupt: &Interrupt,
+    skip_special_tokens: bool,
+) -> tk::Result<Vec<Option<Vec<u8>>>> {
+    let size = tokenizer
+        .get_vocab(true)
+        .into_values()
+        .max()
+        .map_or(0, |id| id + 1);
+    let special: HashSet<u32> = if skip_special_tokens {
+        tokenizer
+            .get_added_tokens_decoder()
+            .into_iter()
+            .filter(|(_, token)| token.special)
+            .map(|(id, _)| id)
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    (0..size)
+        .map(|id| {
+            interrupt.check()?;
+            tokenizer
+                .id_to_token(id)
+                .filter(|_| !special.contains(&id))
+                .map(|token| token_bytes(tokenizer, &token, false))
+                .transpose()
+        })
+        .collect()
+}
+
+/// JSON of the decoder, to tell if a decoder changed without the tokenizer.
+fn decoder_json(tokenizer: &Tokenizer) -> tk::Result<Option<String>> {
+    Ok(tokenizer
+        .get_decoder()
+        .map(serde_json::to_string)
+        .transpose()?)
+}
+
+/// Same as `TokenizerImpl::post_process` without truncation and padding, for
+/// sequences encoded with the untruncated copy of `tokenizer`.
+fn process(
+    tokenizer: &Tokenizer,
+    encoding: Encoding,
+    pair: Option<Encoding>,
+    add_special_tokens: bool,
+) -> tk::Result<Encoding> {
+    match tokenizer.get_post_processor() {
+        Some(processor) => processor.process(encoding, pair, add_special_tokens),
+        None => {
+            let encodings = std::iter::once(encoding).chain(pair).collect();
+            let mut encodings =
+                <dyn PostProcessor>::default_process(encodings, add_special_tokens)?;
+            Ok(encodings.pop().unwrap())
+        }
+    }
+}
+
+/// JSON of a tokenizer, with middle truncation in a key of its own, since
+/// Tokenizers doesn't have it. Tokenizers fails on keys it doesn't know, so
+/// the key is removed before loading.
+#[derive(Serialize)]
+struct SavedTokenizer<'a> {
+    #[serde(flatten)]
+    tokenizer: &'a Tokenizer,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    middle_truncation: Option<MiddleTruncation>,
+}
+
+#[derive(Deserialize)]
+struct SavedTruncation {
+    middle_truncation: Option<MiddleTruncation>,
+}
+
+/// Original and normalized text of each sequence of `input`. Pre-tokenized
+/// sequences have no text.
+fn sequence_texts(
+    tokenizer: &Tokenizer,
+    input: &tk::EncodeInput,
+) -> tk::Result<Vec<(String, String)>> {
+    let sequences = match input {
+        tk::EncodeInput::Single(sequence) => vec![sequence],
+        tk::EncodeInput::Dual(sequence, pair) => vec![sequence, pair],
+    };
+    sequences
+        .into_iter()
+        .filter_map(|sequence| match sequence {
+            tk::InputSequence::Raw(text) => Some(text),
+            _ => None,
+        })
+        .map(|text| {
+            let mut normalized = NormalizedString::from(text.as_ref());
+            if let Some(normalizer) = tokenizer.get_normalizer() {
+                normalizer.normalize(&mut normalized)?;
+            }
+            Ok((text.to_string(), normalized.get().to_string()))
+        })
+        .collect()
+}
+
+/// Unit of the offsets stored in an encoding. `Encoding` methods that take
+/// or return positions use the same unit.
+#[derive(Clone, Copy, Default)]
+enum OffsetUnit {
+    Byte,
+    #[default]
+    Char,
+    Utf16,
+}
+
+impl OffsetUnit {
+    fn new(ruby: &Ruby, value: Value) -> RbResult<Self> {
+        if value.is_nil() {
+            return Ok(Self::default());
+        }
+        let unit = symbol_or_string(value)?;
+        match unit.as_str() {
+            "byte" => Ok(Self::Byte),
+            "char" => Ok(Self::Char),
+            "utf16" => Ok(Self::Utf16),
+            _ => Err(Error::new(
+                ruby.exception_arg_error(),
+                "The offsets value must be 'char', 'byte', or 'utf16'",
+            )),
+        }
+    }
+
+    fn encode(
+        self,
+        tokenizer: &Tokenizer,
+        input: tk::EncodeInput,
+        add_special_tokens: bool,
+    ) -> tk::Result<Encoding> {
+        match self {
+            Self::Byte => tokenizer.encode(input, add_special_tokens),
+            Self::Char => tokenizer.encode_char_offsets(input, add_special_tokens),
+            Self::Utf16 => {
+                let offsets = Utf16Offsets::new(&input);
+                let mut encoding = tokenizer.encode(input, add_special_tokens)?;
+                offsets.convert(&mut encoding);
+                Ok(encoding)
+            }
+        }
+    }
+}
+
+/// Byte to UTF-16 offset tables for each word of each input sequence. Raw
+/// sequences have a single word, since their offsets are relative to the
+/// whole text.
+struct Utf16Offsets(Vec<(bool, Vec<Vec<usize>>)>);
+
+impl Utf16Offsets {
+    fn new(input: &tk::EncodeInput) -> Self {
+        let sequences = match input {
+            tk::EncodeInput::Single(seq) => vec![seq],
+            tk::EncodeInput::Dual(seq, pair) => vec![seq, pair],
+        };
+        Self(
+            sequences
+                .into_iter()
+                .map(|seq| match seq {
+                    tk::InputSequence::Raw(text) => (false, vec![Self::table(text)]),
+                    tk::InputSequence::PreTokenized(words) => {
+                        (true, words.iter().map(|w| Self::table(w)).collect())
+                    }
+                    tk::InputSequence::PreTokenizedOwned(words) => {
+                        (true, words.iter().map(|w| Self::table(w)).collect())
+                    }
+                    tk::InputSequence::PreTokenizedCow(words) => {
+                        (true, words.iter().map(|w| Self::table(w)).collect())
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn table(text: &str) -> Vec<usize> {
+        let mut table = Vec::with_capacity(text.len() + 1);
+        let mut utf16 = 0;
+        for c in text.chars() {
+            // offsets inside a character map to its start
+            table.extend(std::iter::repeat(utf16).take(c.len_utf8()));
+            utf16 += c.len_utf16();
+        }
+        table.push(utf16);
+        table
+    }
+
+    fn convert(&self, encoding: &mut Encoding) {
+        let sequence_ids = encoding.get_sequence_ids();
+        let word_ids = encoding.get_word_ids().to_vec();
+        for (i, offsets) in encoding.get_offsets_mut().iter_mut().enumerate() {
+            // special tokens added by the post-processor have no sequence
+            let (pretokenized, words) = match sequence_ids[i].and_then(|s| self.0.get(s)) {
+                Some(sequence) => sequence,
+                None => continue,
+            };
+            let word = if *pretokenized { word_ids[i] } else { Some(0) };
+            if let Some(table) = word.and_then(|w| words.get(w as usize)) {
+                offsets.0 = table.get(offsets.0).copied().unwrap_or(offsets.0);
+                offsets.1 = table.get(offsets.1).copied().unwrap_or(offsets.1);
+            }
+        }
+        for overflowing in encoding.get_overflowing_mut() {
+            self.convert(overflowing);
+        }
+    }
+}
+
+/// Encodes a batch without offsets and pads it to the longest encoding, so
+/// it can be used as a matrix. The tokenizer's own padding is used when set,
+/// so its direction and pad id are honored.
+fn encode_batch_rectangular<'s>(
+    tokenizer: &Tokenizer,
+    encoder: &Encoder,
+    inputs: Vec<tk::EncodeInput<'s>>,
+    interrupt: &Interrupt,
+    add_special_tokens: bool,
+) -> tk::Result<Vec<Encoding>> {
+    let mut encodings: Vec<Encoding> = encode_batch_interruptible(
+        tokenizer,
+        encoder,
+        inputs,
+        interrupt,
+        add_special_tokens,
+        |tokenizer, input, add_special_tokens| tokenizer.encode_fast(input, add_special_tokens),
+    )?
+    .into_iter()
+    .map(|result| result.encoding)
+    .collect();
+    if encoder.get_padding().is_none() {
+        pad_encodings(&mut encodings, &PaddingParams::default())?;
+    }
+    Ok(encodings)
+}
+
+#[magnus::wrap(class = "Tokenizers::Tokenizer", frozen_shareable)]
 pub struct RbTokenizer {
     tokenizer: Arc<RwLock<Tokenizer>>,
+    /// Set instead of the tokenizer's truncation for the `middle` strategy.
+    middle_truncation: Arc<RwLock<Option<MiddleTruncation>>>,
+    /// Copy without truncation, padding, and post-processor, for encoding
+    /// texts in full. It's made when first needed and dropped when the
+    /// tokenizer changes. Components are shared, so it sees their changes.
+    untruncated: Arc<RwLock<Option<Arc<Tokenizer>>>>,
+    /// Index of token bytes for `heal`, with the JSON of the decoder it was
+    /// made with, since decoders can be changed without the tokenizer.
+    prefix_index: Arc<RwLock<Option<(Option<String>, RbPrefixIndex)>>>,
+    frozen: AtomicBool,
 }
 
 impl Clone for RbTokenizer {
     fn clone(&self) -> Self {
         RbTokenizer {
             tokenizer: Arc::clone(&self.tokenizer),
+            middle_truncation: Arc::clone(&self.middle_truncation),
+            untruncated: Arc::clone(&self.untruncated),
+            prefix_index: Arc::clone(&self.prefix_index),
+            frozen: AtomicBool::new(self.frozen.load(Ordering::Acquire)),
         }
     }
 }
@@ -273,7 +1018,47 @@ impl RbTokenizer {
     pub fn new(tokenizer: Tokenizer) -> Self {
         Self {
             tokenizer: Arc::new(RwLock::new(tokenizer)),
+            middle_truncation: Arc::new(RwLock::new(None)),
+            untruncated: Arc::new(RwLock::new(None)),
+            prefix_index: Arc::new(RwLock::new(None)),
+            frozen: AtomicBool::new(false),
+        }
+    }
+
+    /// Mark the tokenizer as read-only so it can be shared across Ractors;
+    /// any later mutation raises `FrozenError`.
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::Release);
+    }
+
+    fn check_frozen(&self) -> RbResult<()> {
+        if self.frozen.load(Ordering::Acquire) {
+            let ruby = Ruby::get().unwrap();
+            return Err(Error::new(
+                ruby.exception_frozen_error(),
+                "can't modify frozen Tokenizers::Tokenizer",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wraps a component of the tokenizer. Components share their state with
+    /// the tokenizer, so a frozen tokenizer returns frozen copies instead.
+    fn component<T>(&self, ruby: &Ruby, component: &T) -> RbResult<Obj<T>>
+    where
+        T: TypedData + Clone + Serialize + DeserializeOwned,
+    {
+        if !self.frozen.load(Ordering::Acquire) {
+            return Ok(ruby.obj_wrap(component.clone()));
         }
+        let copy = serde_json::to_value(component)
+            .and_then(serde_json::from_value)
+            .map_err(|e| {
+                RbError::new_err(format!("Error while attempting to copy component: {}", e))
+            })?;
+        let component = ruby.obj_wrap(copy);
+        component.freeze();
+        Ok(component)
     }
 
     /// Acquire the inner tokenizer for reading; surfaces lock poisoning as a
@@ -284,36 +1069,125 @@ impl RbTokenizer {
             .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))
     }
 
+    /// Acquire the inner tokenizer for reading inside `detach`. Guards must not
+    /// be held across `detach`, since a writer waiting for the lock keeps the
+    /// GVL, so the detached thread could never get it back to return.
+    fn read_detached(&self) -> tk::Result<RwLockReadGuard<'_, Tokenizer>> {
+        self.tokenizer
+            .read()
+            .map_err(|_| "Tokenizer RwLock is poisoned".into())
+    }
+
     /// Acquire the inner tokenizer for writing; surfaces lock poisoning as a
     /// `PyException` instead of panicking.
     pub(crate) fn write_inner(&self) -> RbResult<RwLockWriteGuard<'_, Tokenizer>> {
-        self.tokenizer
+        self.check_frozen()?;
+        let tokenizer = self
+            .tokenizer
+            .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))?;
+        // dropped while the lock is held, so they can't be made from the
+        // tokenizer before the change
+        *self
+            .untruncated
+            .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))? = None;
+        *self
+            .prefix_index
             .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))? = None;
+        Ok(tokenizer)
+    }
+
+    /// The untruncated copy of the tokenizer, made from `tokenizer` if needed.
+    /// Taking the read guard keeps the tokenizer from changing meanwhile.
+    fn untruncated(&self, tokenizer: &RwLockReadGuard<'_, Tokenizer>) -> RbResult<Arc<Tokenizer>> {
+        if let Some(untruncated) = &*self
+            .untruncated
+            .read()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))?
+        {
+            return Ok(untruncated.clone());
+        }
+        let mut untruncated = Tokenizer::clone(tokenizer);
+        untruncated
+            .with_truncation(None)
+            .expect("Failed to set truncation to `None`! This should never happen");
+        untruncated
+            .with_padding(None)
+            .with_post_processor(None::<RbPostProcessor>);
+        let untruncated = Arc::new(untruncated);
+        *self
+            .untruncated
+            .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))? =
+            Some(untruncated.clone());
+        Ok(untruncated)
+    }
+
+    fn middle_truncation(&self) -> RbResult<Option<MiddleTruncation>> {
+        self.middle_truncation
+            .read()
+            .map(|middle| *middle)
             .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))
     }
 
+    fn set_middle_truncation(&self, middle: Option<MiddleTruncation>) -> RbResult<()> {
+        *self
+            .middle_truncation
+            .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))? = middle;
+        Ok(())
+    }
+
     pub fn from_model(model: &RbModel) -> Self {
         RbTokenizer::new(TokenizerImpl::new(model.clone()))
     }
 
     pub fn from_str(json: RString) -> RbResult<Self> {
-        let tokenizer = Tokenizer::from_str(unsafe { json.as_str()? }).map_err(RbError::from);
-        Ok(Self::new(tokenizer?))
+        Self::from_json(unsafe { json.as_str()? })
     }
 
     pub fn from_file(path: PathBuf) -> RbResult<Self> {
-        let tokenizer = Tokenizer::from_file(path).map_err(RbError::from);
-        Ok(Self::new(tokenizer?))
+        let json = std::fs::read_to_string(path).map_err(|e| RbError::from(e.into()))?;
+        Self::from_json(&json)
+    }
+
+    fn from_json(json: &str) -> RbResult<Self> {
+        let saved: SavedTruncation =
+            serde_json::from_str(json).map_err(|e| RbError::from(e.into()))?;
+        let tokenizer = if saved.middle_truncation.is_some() {
+            let mut value: serde_json::Value =
+                serde_json::from_str(json).map_err(|e| RbError::from(e.into()))?;
+            if let Some(object) = value.as_object_mut() {
+                object.remove("middle_truncation");
+            }
+            Tokenizer::from_str(&value.to_string())
+        } else {
+            Tokenizer::from_str(json)
+        }
+        .map_err(RbError::from)?;
+        let tokenizer = Self::new(tokenizer);
+        tokenizer.set_middle_truncation(saved.middle_truncation)?;
+        Ok(tokenizer)
     }
 
     pub fn to_str(&self, pretty: bool) -> RbResult<String> {
-        self.read_inner()?.to_string(pretty).map_err(RbError::from)
+        let saved = SavedTokenizer {
+            tokenizer: &self.read_inner()?,
+            middle_truncation: self.middle_truncation()?,
+        };
+        if pretty {
+            serde_json::to_string_pretty(&saved)
+        } else {
+            serde_json::to_string(&saved)
+        }
+        .map_err(|e| RbError::from(e.into()))
     }
 
     pub fn save(&self, path: String, pretty: bool) -> RbResult<()> {
-        self.read_inner()?
-            .save(&path, pretty)
-            .map_err(RbError::from)
+        let json = self.to_str(pretty)?;
+        std::fs::write(path, json).map_err(|e| RbError::from(e.into()))
     }
 
     pub fn num_special_tokens_to_add(&self, is_pair: bool) -> RbResult<usize> {
@@ -347,68 +1221,32 @@ impl RbTokenizer {
         max_length: usize,
         kwargs: RHash,
     ) -> RbResult<()> {
-        let mut params = TruncationParams {
-            max_length,
-            ..Default::default()
-        };
-
-        let value: Value = kwargs.delete(ruby.to_symbol("stride"))?;
-        if !value.is_nil() {
-            params.stride = TryConvert::try_convert(value)?;
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("strategy"))?;
-        if !value.is_nil() {
-            let strategy_str = String::try_convert(value)?;
-            params.strategy = match strategy_str.as_str() {
-                "longest_first" => TruncationStrategy::LongestFirst,
-                "only_first" => TruncationStrategy::OnlyFirst,
-                "only_second" => TruncationStrategy::OnlySecond,
-                _ => return Err(Error::new(
-                    ruby.exception_arg_error(),
-                    "The strategy value must be 'longest_first', 'only_first', or 'only_second'",
-                )),
-            }
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("direction"))?;
-        if !value.is_nil() {
-            let dir_str = String::try_convert(value)?;
-            params.direction = match dir_str.as_str() {
-                "left" => TruncationDirection::Left,
-                "right" => TruncationDirection::Right,
-                _ => {
-                    return Err(Error::new(
-                        ruby.exception_arg_error(),
-                        "The direction value must be 'left' or 'right'",
-                    ))
-                }
-            }
-        }
-
-        if !kwargs.is_empty() {
-            // TODO improve message
-            return Err(Error::new(ruby.exception_arg_error(), "unknown keyword"));
-        }
-
-        if let Err(error_message) = rb_self.write_inner()?.with_truncation(Some(params)) {
-            return Err(Error::new(
-                ruby.exception_arg_error(),
-                error_message.to_string(),
-            ));
-        }
-
-        Ok(())
+        let truncation = truncation_params(ruby, max_length, kwargs)?;
+        let mut tokenizer = rb_self.write_inner()?;
+        let middle = Truncation::set(Some(&truncation), &mut tokenizer)
+            .map_err(|e| Error::new(ruby.exception_arg_error(), e.to_string()))?;
+        rb_self.set_middle_truncation(middle)
     }
 
     pub fn no_truncation(&self) -> RbResult<()> {
         self.write_inner()?
             .with_truncation(None)
             .expect("Failed to set truncation to `None`! This should never happen");
-        Ok(())
+        self.set_middle_truncation(None)
     }
 
     pub fn get_truncation(ruby: &Ruby, rb_self: &Self) -> RbResult<Option<RHash>> {
+        if let Some(middle) = rb_self.middle_truncation()? {
+            let ret_hash = ruby.hash_new();
+
+            ret_hash.aset("max_length", middle.max_length)?;
+            ret_hash.aset("stride", middle.stride)?;
+            ret_hash.aset("strategy", "middle")?;
+            ret_hash.aset("head_ratio", middle.head_ratio)?;
+
+            return Ok(Some(ret_hash));
+        }
+
         rb_self
             .read_inner()?
             .get_truncation()
@@ -424,59 +1262,9 @@ impl RbTokenizer {
             })
     }
 
-    // TODO support more kwargs
     pub fn enable_padding(ruby: &Ruby, rb_self: &Self, kwargs: RHash) -> RbResult<()> {
-        let mut params = PaddingParams::default();
-
-        let value: Value = kwargs.delete(ruby.to_symbol("direction"))?;
-        if !value.is_nil() {
-            let dir_str = String::try_convert(value)?;
-            params.direction = match dir_str.as_str() {
-                "left" => PaddingDirection::Left,
-                "right" => PaddingDirection::Right,
-                _ => {
-                    return Err(Error::new(
-                        ruby.exception_arg_error(),
-                        "The direction value must be 'left' or 'right'",
-                    ))
-                }
-            }
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("pad_to_multiple_of"))?;
-        if !value.is_nil() {
-            params.pad_to_multiple_of = TryConvert::try_convert(value)?;
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("pad_id"))?;
-        if !value.is_nil() {
-            params.pad_id = TryConvert::try_convert(value)?;
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("pad_type_id"))?;
-        if !value.is_nil() {
-            params.pad_type_id = TryConvert::try_convert(value)?;
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("pad_token"))?;
-        if !value.is_nil() {
-            params.pad_token = TryConvert::try_convert(value)?;
-        }
-
-        let value: Value = kwargs.delete(ruby.to_symbol("length"))?;
-        if value.is_nil() {
-            params.strategy = PaddingStrategy::BatchLongest;
-        } else {
-            params.strategy = PaddingStrategy::Fixed(TryConvert::try_convert(value)?);
-        }
-
-        if !kwargs.is_empty() {
-            // TODO improve message
-            return Err(Error::new(ruby.exception_arg_error(), "unknown keyword"));
-        }
-
+        let params = padding_params(ruby, kwargs)?;
         rb_self.write_inner()?.with_padding(Some(params));
-
         Ok(())
     }
 
@@ -509,13 +1297,22 @@ impl RbTokenizer {
             })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn encode(
-        &self,
+        ruby: &Ruby,
+        rb_self: &Self,
         sequence: Value,
         pair: Option<Value>,
         is_pretokenized: bool,
         add_special_tokens: bool,
+        truncation: Value,
+        padding: Value,
+        offsets: Value,
+        keep_text: bool,
     ) -> RbResult<RbEncoding> {
+        let mut options = EncodeOptions::new(ruby, truncation, padding)?;
+        options.keep_text = keep_text;
+        let unit = OffsetUnit::new(ruby, offsets)?;
         let sequence: tk::InputSequence = if is_pretokenized {
             PreTokenizedInputSequence::try_convert(sequence)?.into()
         } else {
@@ -533,66 +1330,382 @@ impl RbTokenizer {
             None => tk::EncodeInput::Single(sequence),
         };
 
-        self.read_inner()?
-            .encode_char_offsets(input, add_special_tokens)
-            .map(|v| RbEncoding { encoding: v })
+        let tokenizer = rb_self.read_inner()?;
+        options
+            .apply(ruby, rb_self, &tokenizer)?
+            .encode(
+                &tokenizer,
+                input,
+                add_special_tokens,
+                |tokenizer, input, add_special_tokens| {
+                    unit.encode(tokenizer, input, add_special_tokens)
+                },
+            )
             .map_err(RbError::from)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn encode_batch(
         ruby: &Ruby,
         rb_self: &Self,
         input: RArray,
         is_pretokenized: bool,
         add_special_tokens: bool,
+        threads: Option<usize>,
+        truncation: Value,
+        padding: Value,
+        offsets: Value,
+        keep_text: bool,
     ) -> RbResult<RArray> {
-        let input: Vec<tk::EncodeInput> = input
-            .into_iter()
-            .map(|o| {
-                let input: tk::EncodeInput = if is_pretokenized {
-                    PreTokenizedEncodeInput::try_convert(o)?.into()
-                } else {
-                    TextEncodeInput::try_convert(o)?.into()
-                };
-                Ok(input)
+        let threads = parallelism::check_threads(ruby, threads)?;
+        let mut options = EncodeOptions::new(ruby, truncation, padding)?;
+        options.keep_text = keep_text;
+        let unit = OffsetUnit::new(ruby, offsets)?;
+        let input = batch_inputs(input, is_pretokenized)?;
+        let encoder = options.apply(ruby, rb_self, &rb_self.read_inner()?)?;
+        ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            parallelism::install(threads, || {
+                encode_batch_interruptible(
+                    &tokenizer,
+                    &encoder,
+                    input,
+                    interrupt,
+                    add_special_tokens,
+                    |tokenizer, input, add_special_tokens| {
+                        unit.encode(tokenizer, input, add_special_tokens)
+                    },
+                )
             })
-            .collect::<RbResult<Vec<tk::EncodeInput>>>()?;
-        ruby.detach(|| {
-            rb_self
-                .tokenizer
-                .read()
-                .unwrap()
-                .encode_batch_char_offsets(input, add_special_tokens)
         })
-        .map(|encodings| ruby.ary_from_iter(encodings.into_iter().map(Into::<RbEncoding>::into)))
-        .map_err(RbError::from)
+        .map(|encodings| ruby.ary_from_iter(encodings))
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn encode_batch_fast(
         ruby: &Ruby,
         rb_self: &Self,
         input: RArray,
         is_pretokenized: bool,
         add_special_tokens: bool,
+        threads: Option<usize>,
+        truncation: Value,
+        padding: Value,
     ) -> RbResult<RArray> {
-        let mut items = Vec::<tk::EncodeInput>::with_capacity(input.len());
-        for item in input {
-            let item: tk::EncodeInput = if is_pretokenized {
-                PreTokenizedEncodeInput::try_convert(item)?.into()
-            } else {
-                TextEncodeInput::try_convert(item)?.into()
-            };
-            items.push(item);
+        let threads = parallelism::check_threads(ruby, threads)?;
+        let options = EncodeOptions::new(ruby, truncation, padding)?;
+        let input = batch_inputs(input, is_pretokenized)?;
+        let encoder = options.apply(ruby, rb_self, &rb_self.read_inner()?)?;
+        ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            parallelism::install(threads, || {
+                encode_batch_interruptible(
+                    &tokenizer,
+                    &encoder,
+                    input,
+                    interrupt,
+                    add_special_tokens,
+                    |tokenizer, input, add_special_tokens| {
+                        tokenizer.encode_fast(input, add_special_tokens)
+                    },
+                )
+            })
+        })
+        .map(|encodings| ruby.ary_from_iter(encodings))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_batch_packed(
+        ruby: &Ruby,
+        rb_self: &Self,
+        input: RArray,
+        dtype: Value,
+        is_pretokenized: bool,
+        add_special_tokens: bool,
+        threads: Option<usize>,
+        truncation: Value,
+        padding: Value,
+    ) -> RbResult<PackedBatchArgs> {
+        let dtype = DType::new(ruby, dtype)?;
+        let threads = parallelism::check_threads(ruby, threads)?;
+        let options = EncodeOptions::new(ruby, truncation, padding)?;
+        let input = batch_inputs(input, is_pretokenized)?;
+        let encoder = options.apply(ruby, rb_self, &rb_self.read_inner()?)?;
+        ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            let encodings = parallelism::install(threads, || {
+                encode_batch_rectangular(&tokenizer, &encoder, input, interrupt, add_special_tokens)
+            })?;
+            PackedBatch::new(&encodings, dtype)
+        })
+        .map(|batch| batch.into_args(ruby))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_for_model(
+        ruby: &Ruby,
+        rb_self: &Self,
+        input: RArray,
+        names: RArray,
+        dtype: Value,
+        is_pretokenized: bool,
+        add_special_tokens: bool,
+        threads: Option<usize>,
+        truncation: Value,
+        padding: Value,
+    ) -> RbResult<RHash> {
+        let names = names
+            .into_iter()
+            .map(|name| ModelInput::new(ruby, name))
+            .collect::<RbResult<Vec<_>>>()?;
+        let dtype = if dtype.is_nil() {
+            None
+        } else {
+            Some(DType::new(ruby, dtype)?)
+        };
+        let threads = parallelism::check_threads(ruby, threads)?;
+        let options = EncodeOptions::new(ruby, truncation, padding)?;
+        let input = batch_inputs(input, is_pretokenized)?;
+        let encoder = options.apply(ruby, rb_self, &rb_self.read_inner()?)?;
+        let encodings = ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            parallelism::install(threads, || {
+                encode_batch_rectangular(&tokenizer, &encoder, input, interrupt, add_special_tokens)
+            })
+        })?;
+
+        let hash = ruby.hash_new();
+        for name in names {
+            let key = ruby.to_symbol(name.name());
+            match dtype {
+                Some(dtype) => {
+                    let mut buffer = Vec::new();
+                    for encoding in &encodings {
+                        for value in name.row(encoding) {
+                            dtype.push(&mut buffer, value).map_err(RbError::from)?;
+                        }
+                    }
+                    hash.aset(key, ruby.str_from_slice(&buffer))?;
+                }
+                None => {
+                    let rows = encodings.iter().map(|e| ruby.ary_from_vec(name.row(e)));
+                    hash.aset(key, ruby.ary_from_iter(rows))?;
+                }
+            }
         }
-        ruby.detach(|| {
-            rb_self
-                .tokenizer
-                .read()
-                .unwrap()
-                .encode_batch_fast(items, add_special_tokens)
+        Ok(hash)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_fields(
+        ruby: &Ruby,
+        rb_self: &Self,
+        names: Vec<String>,
+        texts: Vec<String>,
+        max_length: usize,
+        priorities: Option<HashMap<String, i64>>,
+        template: Option<String>,
+    ) -> RbResult<RbEncoding> {
+        if names.is_empty() {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "fields can't be empty",
+            ));
+        }
+        let priorities = match priorities {
+            Some(priorities) => {
+                if let Some(name) = priorities.keys().find(|name| !names.contains(name)) {
+                    return Err(Error::new(
+                        ruby.exception_arg_error(),
+                        format!("Unknown field in priorities: {}", name),
+                    ));
+                }
+                names
+                    .iter()
+                    .map(|name| priorities.get(name).copied().unwrap_or(0))
+                    .collect()
+            }
+            None => vec![0; names.len()],
+        };
+
+        let tokenizer = rb_self.read_inner()?;
+        let template = template
+            .map(|template| {
+                FieldTemplate::parse(&template, &names, |token| tokenizer.token_to_id(token))
+            })
+            .transpose()
+            .map_err(|e| Error::new(ruby.exception_arg_error(), e))?;
+        let num_special_tokens = match &template {
+            Some(template) => template.num_special_tokens(),
+            None if names.len() <= 2 => tokenizer
+                .get_post_processor()
+                .map_or(0, |p| p.added_tokens(names.len() == 2)),
+            None => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "template is required for more than 2 fields",
+                ))
+            }
+        };
+        let budget = match max_length.checked_sub(num_special_tokens) {
+            Some(budget) => budget,
+            None => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    format!(
+                        "max_length must be at least {} to fit special tokens",
+                        num_special_tokens
+                    ),
+                ))
+            }
+        };
+
+        let untruncated = rb_self.untruncated(&tokenizer)?;
+        let encodings = texts
+            .iter()
+            .map(|text| {
+                let encoding = untruncated.encode_char_offsets(text.as_str(), false)?;
+                process(&tokenizer, encoding, None, false)
+            })
+            .collect::<tk::Result<Vec<_>>>()
+            .map_err(RbError::from)?;
+        let lengths: Vec<usize> = encodings.iter().map(|e| e.len()).collect();
+        let allocation = fields::allocate(&lengths, &priorities, budget);
+
+        let mut encoding = match template {
+            Some(template) => template.apply(&encodings, &allocation),
+            None => {
+                let mut fields = encodings.iter().zip(&allocation).zip(0..).map(
+                    |((encoding, &length), type_id)| select_as(encoding, &[0..length], type_id),
+                );
+                let first = fields.next().unwrap();
+                process(&tokenizer, first, fields.next(), true).map_err(RbError::from)?
+            }
+        };
+        if let Some(params) = tokenizer.get_padding() {
+            pad_encodings(slice::from_mut(&mut encoding), params).map_err(RbError::from)?;
+        }
+        Ok(RbEncoding {
+            encoding,
+            original_length: Some(lengths.iter().sum::<usize>() + num_special_tokens),
+            texts: Vec::new(),
         })
-        .map(|encodings| ruby.ary_from_iter(encodings.into_iter().map(Into::<RbEncoding>::into)))
-        .map_err(RbError::from)
+    }
+
+    pub fn count_tokens(&self, text: String, add_special_tokens: bool) -> RbResult<usize> {
+        let tokenizer = self.read_inner()?;
+        self.untruncated(&tokenizer)?
+            .encode_fast(text, false)
+            .and_then(|encoding| process(&tokenizer, encoding, None, add_special_tokens))
+            .map(|encoding| encoding.len())
+            .map_err(RbError::from)
+    }
+
+    pub fn count_tokens_batch(
+        ruby: &Ruby,
+        rb_self: &Self,
+        texts: Vec<String>,
+        add_special_tokens: bool,
+        threads: Option<usize>,
+    ) -> RbResult<Vec<usize>> {
+        let threads = parallelism::check_threads(ruby, threads)?;
+        let untruncated = rb_self.untruncated(&rb_self.read_inner()?)?;
+        ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            parallelism::install(threads, || {
+                texts
+                    .into_maybe_par_iter()
+                    .map(|text| {
+                        interrupt.check()?;
+                        let encoding = untruncated.encode_fast(text, false)?;
+                        process(&tokenizer, encoding, None, add_special_tokens)
+                            .map(|encoding| encoding.len())
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    pub fn truncate_text(
+        ruby: &Ruby,
+        rb_self: &Self,
+        text: String,
+        max_tokens: usize,
+        side: Value,
+    ) -> RbResult<String> {
+        let side = match symbol_or_string(side)?.as_str() {
+            "right" => Side::Right,
+            "left" => Side::Left,
+            "middle" => Side::Middle,
+            _ => {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The side value must be 'right', 'left', or 'middle'",
+                ))
+            }
+        };
+        let tokenizer = rb_self.read_inner()?;
+        let encoding = rb_self
+            .untruncated(&tokenizer)?
+            .encode(text.as_str(), false)
+            .and_then(|encoding| process(&tokenizer, encoding, None, false))
+            .map_err(RbError::from)?;
+        Ok(text::truncate(
+            &text,
+            encoding.get_offsets(),
+            max_tokens,
+            side,
+        ))
+    }
+
+    pub fn chunk(
+        ruby: &Ruby,
+        rb_self: &Self,
+        text: String,
+        max_tokens: usize,
+        overlap: usize,
+        boundaries: RArray,
+    ) -> RbResult<RArray> {
+        if max_tokens == 0 || overlap >= max_tokens {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "overlap must be less than max_tokens",
+            ));
+        }
+        let boundaries = boundaries
+            .into_iter()
+            .map(|value| match symbol_or_string(value)?.as_str() {
+                "paragraph" => Ok(Boundary::Paragraph),
+                "sentence" => Ok(Boundary::Sentence),
+                "word" => Ok(Boundary::Word),
+                _ => Err(Error::new(
+                    ruby.exception_arg_error(),
+                    "The boundaries values must be 'paragraph', 'sentence', or 'word'",
+                )),
+            })
+            .collect::<RbResult<Vec<_>>>()?;
+        let tokenizer = rb_self.read_inner()?;
+        let encoding = rb_self
+            .untruncated(&tokenizer)?
+            .encode(text.as_str(), false)
+            .and_then(|encoding| process(&tokenizer, encoding, None, false))
+            .map_err(RbError::from)?;
+
+        let chunks = ruby.ary_new();
+        for chunk in text::chunk(
+            &text,
+            encoding.get_offsets(),
+            max_tokens,
+            overlap,
+            &boundaries,
+        ) {
+            let hash = ruby.hash_new();
+            hash.aset(ruby.to_symbol("text"), chunk.text)?;
+            hash.aset(ruby.to_symbol("offsets"), (chunk.start, chunk.end))?;
+            hash.aset(ruby.to_symbol("num_tokens"), chunk.num_tokens)?;
+            chunks.push(hash)?;
+        }
+        Ok(chunks)
     }
 
     pub fn decode(&self, ids: Vec<u32>, skip_special_tokens: bool) -> RbResult<String> {
@@ -606,16 +1719,21 @@ impl RbTokenizer {
         rb_self: &Self,
         sequences: Vec<Vec<u32>>,
         skip_special_tokens: bool,
+        threads: Option<usize>,
     ) -> RbResult<Vec<String>> {
-        ruby.detach(|| {
-            let slices = sequences.iter().map(|v| &v[..]).collect::<Vec<&[u32]>>();
-            rb_self
-                .tokenizer
-                .read()
-                .unwrap()
-                .decode_batch(&slices, skip_special_tokens)
+        let threads = parallelism::check_threads(ruby, threads)?;
+        ruby.detach(|interrupt| {
+            let tokenizer = rb_self.tokenizer.read().unwrap();
+            parallelism::install(threads, || {
+                sequences
+                    .into_maybe_par_iter()
+                    .map(|ids| {
+                        interrupt.check()?;
+                        tokenizer.decode(&ids, skip_special_tokens)
+                    })
+                    .collect()
+            })
         })
-        .map_err(RbError::from)
     }
 
     pub fn token_to_id(&self, token: String) -> RbResult<Option<u32>> {
@@ -626,6 +1744,191 @@ impl RbTokenizer {
         Ok(self.read_inner()?.id_to_token(id))
     }
 
+    /// Tokens are decoded as if they follow other tokens, so ones that start a
+    /// word, like WordPiece tokens without `##`, include the space before it.
+    pub fn token_bytes(ruby: &Ruby, rb_self: &Self, id: u32) -> RbResult<Option<RString>> {
+        Ok(rb_self
+            .decoded_token(id, false)?
+            .map(|bytes| ruby.str_from_slice(&bytes)))
+    }
+
+    /// Bytes of token `id` in decoded text, at the start of it with `start`.
+    pub(crate) fn decoded_token(&self, id: u32, start: bool) -> RbResult<Option<Vec<u8>>> {
+        let tokenizer = self.read_inner()?;
+        tokenizer
+            .id_to_token(id)
+            .map(|token| token_bytes(&tokenizer, &token, start))
+            .transpose()
+            .map_err(RbError::from)
+    }
+
+    /// Content of token `id` when it's a special token.
+    pub(crate) fn special_token(&self, id: u32) -> RbResult<Option<String>> {
+        let tokenizer = self.read_inner()?;
+        Ok(tokenizer
+            .get_added_vocabulary()
+            .get_added_tokens_decoder()
+            .get(&id)
+            .filter(|token| token.special)
+            .map(|token| token.content.clone()))
+    }
+
+    pub fn vocab_bytes(ruby: &Ruby, rb_self: &Self) -> RbResult<RArray> {
+        Ok(ruby.ary_from_iter(
+            rb_self
+                .decoded_vocab(ruby, false)?
+                .into_iter()
+                .map(|bytes| bytes.map(|bytes| ruby.str_from_slice(&bytes))),
+        ))
+    }
+
+    /// Distinct ids a word can be encoded to. Contexts are encoded in full,
+    /// so the pipeline adds prefixes like it does in text, and only the
+    /// tokens of the word are kept.
+    pub fn token_variants(
+        ruby: &Ruby,
+        rb_self: &Self,
+        word: String,
+        casing: bool,
+        spacing: bool,
+    ) -> RbResult<Vec<Vec<u32>>> {
+        if word.is_empty() {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "word can't be empty",
+            ));
+        }
+        let mut words = vec![word.clone()];
+        if casing {
+            for upper in [true, false] {
+                let cased = with_first_case(&word, upper);
+                if !words.contains(&cased) {
+                    words.push(cased);
+                }
+            }
+        }
+        // text before the word, and where the tokens of the word start
+        let contexts: &[(&str, usize)] = if spacing {
+            &[("", 0), ("x ", 1), ("x\n", 2)]
+        } else {
+            &[("", 0)]
+        };
+
+        let tokenizer = rb_self.read_inner()?;
+        let untruncated = rb_self.untruncated(&tokenizer)?;
+        let mut variants: Vec<Vec<u32>> = Vec::new();
+        for word in &words {
+            for &(context, start) in contexts {
+                let text = format!("{}{}", context, word);
+                let encoding = untruncated
+                    .encode_char_offsets(text.as_str(), false)
+                    .and_then(|encoding| process(&tokenizer, encoding, None, false))
+                    .map_err(RbError::from)?;
+                let offsets = encoding.get_offsets();
+                // skip contexts that merge with the word
+                if offsets.iter().any(|&(s, e)| s < start && e > start) {
+                    continue;
+                }
+                let ids: Vec<u32> = encoding
+                    .get_ids()
+                    .iter()
+                    .zip(offsets)
+                    .filter(|(_, &(s, _))| s >= start)
+                    .map(|(&id, _)| id)
+                    .collect();
+                if !ids.is_empty() && !variants.contains(&ids) {
+                    variants.push(ids);
+                }
+            }
+        }
+        Ok(variants)
+    }
+
+    pub fn stop_sequence_matcher(
+        ruby: &Ruby,
+        rb_self: &Self,
+        stops: Vec<String>,
+    ) -> RbResult<RbStopSequenceMatcher> {
+        RbStopSequenceMatcher::new(ruby, rb_self.clone(), stops)
+    }
+
+    /// Made once and kept, since `heal` uses it on every call. Special tokens
+    /// are left out, like in `decode`.
+    pub fn prefix_index(ruby: &Ruby, rb_self: &Self) -> RbResult<RbPrefixIndex> {
+        let decoder = decoder_json(&rb_self.read_inner()?).map_err(RbError::from)?;
+        if let Some((cached, index)) = &*rb_self
+            .prefix_index
+            .read()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))?
+        {
+            if *cached == decoder {
+                return Ok(index.clone());
+            }
+        }
+        // the decoder is read again with the vocab, so they match
+        let (decoder, vocab) = ruby.detach(|interrupt| {
+            let tokenizer = rb_self.read_detached()?;
+            Ok((
+                decoder_json(&tokenizer)?,
+                decoded_vocab(&tokenizer, interrupt, true)?,
+            ))
+        })?;
+        let index = RbPrefixIndex::new(vocab);
+        *rb_self
+            .prefix_index
+            .write()
+            .map_err(|_| RbError::new_err("Tokenizer RwLock is poisoned"))? =
+            Some((decoder, index.clone()));
+        Ok(index)
+    }
+
+    /// Removes the last tokens when their bytes could be the start of a longer
+    /// token, and returns the remaining ids with a constraint that makes the
+    /// next tokens start with the removed bytes.
+    pub fn heal(
+        ruby: &Ruby,
+        rb_self: &Self,
+        ids: Vec<u32>,
+        index: &RbPrefixIndex,
+    ) -> RbResult<RHash> {
+        let tokenizer = rb_self.read_inner()?;
+        let mut kept = ids.len();
+        let mut prefix = Vec::new();
+        while kept > 0 {
+            let token = match tokenizer.id_to_token(ids[kept - 1]) {
+                Some(token) => token,
+                None => break,
+            };
+            let mut removed = token_bytes(&tokenizer, &token, false).map_err(RbError::from)?;
+            removed.extend_from_slice(&prefix);
+            if !index.is_extended(&removed) {
+                break;
+            }
+            prefix = removed;
+            kept -= 1;
+        }
+
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("ids"), ids[..kept].to_vec())?;
+        hash.aset(ruby.to_symbol("prefix"), ruby.str_from_slice(&prefix))?;
+        hash.aset(
+            ruby.to_symbol("constraint"),
+            RbConstraint::prefix(index, &prefix),
+        )?;
+        Ok(hash)
+    }
+
+    /// Bytes of each token in decoded text, indexed by id.
+    pub(crate) fn decoded_vocab(
+        &self,
+        ruby: &Ruby,
+        skip_special_tokens: bool,
+    ) -> RbResult<Vec<Option<Vec<u8>>>> {
+        ruby.detach(|interrupt| {
+            decoded_vocab(&self.read_detached()?, interrupt, skip_special_tokens)
+        })
+    }
+
     pub fn set_encode_special_tokens(&self, value: bool) -> RbResult<()> {
         self.write_inner()?.set_encode_special_tokens(value);
         Ok(())
@@ -649,19 +1952,103 @@ impl RbTokenizer {
             .map_err(RbError::from)
     }
 
-    pub fn train(&self, files: Vec<String>, trainer: Option<&RbTrainer>) -> RbResult<()> {
-        let mut trainer = match trainer {
+    pub fn train(
+        ruby: &Ruby,
+        rb_self: &Self,
+        files: Vec<String>,
+        trainer: Option<&RbTrainer>,
+        progress: Option<Value>,
+        threads: Option<usize>,
+    ) -> RbResult<()> {
+        let threads = parallelism::check_threads(ruby, threads)?;
+        rb_self.check_frozen()?;
+        let mut tokenizer = rb_self.read_inner()?.clone();
+        let trainer = match trainer {
             Some(t) => t.clone(),
-            None => self.read_inner()?.get_model().get_trainer(),
+            None => tokenizer.get_model().get_trainer(),
         };
-        self.write_inner()?
-            .train_from_files(&mut trainer, files)
-            .map(|_| {})
-            .map_err(RbError::from)
+        // train a copy so the tokenizer stays usable from other threads
+        let model = tokenizer.get_model().model.read().unwrap().clone();
+        tokenizer.with_model(model);
+
+        let total = files
+            .iter()
+            .map(|f| std::fs::metadata(f).map_or(0, |m| m.len()))
+            .sum();
+        let (sender, receiver) = mpsc::channel();
+        let interrupt = Arc::new(Interrupt::default());
+        let mut trainer =
+            ObservedTrainer::new(trainer, interrupt.clone(), Progress::new(sender, total));
+        let handle = thread::spawn(move || {
+            parallelism::install(threads, || {
+                tokenizer.train_from_files(&mut trainer, files)?;
+                Ok((tokenizer.get_model().clone(), trainer.into_special_tokens()))
+            })
+        });
+
+        let result = Self::wait_for_training(ruby, receiver, &interrupt, progress);
+        if result.is_err() {
+            interrupt.set();
+        }
+        // wait for the training thread even when stopping early, so it
+        // doesn't keep running after the call returns
+        let trained = ruby.detach(|_| {
+            handle
+                .join()
+                .map_err(|_| "Training thread panicked".into())
+                .and_then(|trained| trained)
+        });
+        result?;
+        let (model, special_tokens) = trained?;
+
+        let mut tokenizer = rb_self.write_inner()?;
+        // replace the model in place so existing references see the result,
+        // and keep the tokens added while training
+        *tokenizer.get_model().model.write().unwrap() = model.model.read().unwrap().clone();
+        tokenizer
+            .add_special_tokens(special_tokens)
+            .map_err(RbError::from)?;
+        Ok(())
     }
 
-    pub fn get_model(&self) -> RbResult<RbModel> {
-        Ok(self.read_inner()?.get_model().clone())
+    /// Waits without the GVL for the training thread to finish, calling the
+    /// progress block as events arrive.
+    fn wait_for_training(
+        ruby: &Ruby,
+        receiver: Receiver<ProgressEvent>,
+        interrupt: &Interrupt,
+        progress: Option<Value>,
+    ) -> RbResult<()> {
+        let receiver = Mutex::new(receiver);
+        loop {
+            let event = ruby.detach(|detached| loop {
+                if let Err(e) = detached.check() {
+                    interrupt.set();
+                    return Err(e);
+                }
+                match receiver
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(Duration::from_millis(100))
+                {
+                    Ok(event) => return Ok(Some(event)),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(None),
+                }
+            })?;
+            match (event, progress) {
+                (Some((stage, current, total)), Some(block)) => {
+                    let _: Value =
+                        block.funcall("call", (ruby.to_symbol(stage), current, total))?;
+                }
+                (Some(_), None) => {}
+                (None, _) => return Ok(()),
+            }
+        }
+    }
+
+    pub fn get_model(ruby: &Ruby, rb_self: &Self) -> RbResult<Obj<RbModel>> {
+        rb_self.component(ruby, rb_self.read_inner()?.get_model())
     }
 
     pub fn set_model(&self, model: &RbModel) -> RbResult<()> {
@@ -669,8 +2056,12 @@ impl RbTokenizer {
         Ok(())
     }
 
-    pub fn get_normalizer(&self) -> RbResult<Option<RbNormalizer>> {
-        Ok(self.read_inner()?.get_normalizer().cloned())
+    pub fn get_normalizer(ruby: &Ruby, rb_self: &Self) -> RbResult<Option<Obj<RbNormalizer>>> {
+        let tokenizer = rb_self.read_inner()?;
+        tokenizer
+            .get_normalizer()
+            .map(|normalizer| rb_self.component(ruby, normalizer))
+            .transpose()
     }
 
     pub fn set_normalizer(&self, normalizer: Option<&RbNormalizer>) -> RbResult<()> {
@@ -680,8 +2071,12 @@ impl RbTokenizer {
             .map_err(RbError::from)
     }
 
-    pub fn get_pre_tokenizer(&self) -> RbResult<Option<RbPreTokenizer>> {
-        Ok(self.read_inner()?.get_pre_tokenizer().cloned())
+    pub fn get_pre_tokenizer(ruby: &Ruby, rb_self: &Self) -> RbResult<Option<Obj<RbPreTokenizer>>> {
+        let tokenizer = rb_self.read_inner()?;
+        tokenizer
+            .get_pre_tokenizer()
+            .map(|pretok| rb_self.component(ruby, pretok))
+            .transpose()
     }
 
     pub fn set_pre_tokenizer(&self, pretok: Option<&RbPreTokenizer>) -> RbResult<()> {
@@ -689,8 +2084,15 @@ impl RbTokenizer {
         Ok(())
     }
 
-    pub fn get_post_processor(&self) -> RbResult<Option<RbPostProcessor>> {
-        Ok(self.read_inner()?.get_post_processor().cloned())
+    pub fn get_post_processor(
+        ruby: &Ruby,
+        rb_self: &Self,
+    ) -> RbResult<Option<Obj<RbPostProcessor>>> {
+        let tokenizer = rb_self.read_inner()?;
+        tokenizer
+            .get_post_processor()
+            .map(|processor| rb_self.component(ruby, processor))
+            .transpose()
     }
 
     pub fn set_post_processor(&self, processor: Option<&RbPostProcessor>) -> RbResult<()> {
@@ -698,8 +2100,12 @@ impl RbTokenizer {
         Ok(())
     }
 
-    pub fn get_decoder(&self) -> RbResult<Option<RbDecoder>> {
-        Ok(self.read_inner()?.get_decoder().cloned())
+    pub fn get_decoder(ruby: &Ruby, rb_self: &Self) -> RbResult<Option<Obj<RbDecoder>>> {
+        let tokenizer = rb_self.read_inner()?;
+        tokenizer
+            .get_decoder()
+            .map(|decoder| rb_self.component(ruby, decoder))
+            .transpose()
     }
 
     pub fn set_decoder(&self, decoder: Option<&RbDecoder>) -> RbResult<()> {
@@ -713,6 +2119,7 @@ pub fn init_tokenizer(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     class.define_singleton_method("new", function!(RbTokenizer::from_model, 1))?;
     class.define_singleton_method("from_str", function!(RbTokenizer::from_str, 1))?;
     class.define_singleton_method("from_file", function!(RbTokenizer::from_file, 1))?;
+    class.define_method("_freeze", method!(RbTokenizer::freeze, 0))?;
     class.define_method("_to_s", method!(RbTokenizer::to_str, 1))?;
     class.define_method("_save", method!(RbTokenizer::save, 2))?;
     class.define_method(
@@ -734,16 +2141,41 @@ pub fn init_tokenizer(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     class.define_method("_enable_padding", method!(RbTokenizer::enable_padding, 1))?;
     class.define_method("no_padding", method!(RbTokenizer::no_padding, 0))?;
     class.define_method("padding", method!(RbTokenizer::get_padding, 0))?;
-    class.define_method("_encode", method!(RbTokenizer::encode, 4))?;
-    class.define_method("_encode_batch", method!(RbTokenizer::encode_batch, 3))?;
+    class.define_method("_encode", method!(RbTokenizer::encode, 8))?;
+    class.define_method("_encode_batch", method!(RbTokenizer::encode_batch, 8))?;
     class.define_method(
         "_encode_batch_fast",
-        method!(RbTokenizer::encode_batch_fast, 3),
+        method!(RbTokenizer::encode_batch_fast, 6),
+    )?;
+    class.define_method(
+        "_encode_batch_packed",
+        method!(RbTokenizer::encode_batch_packed, 8),
+    )?;
+    class.define_method(
+        "_encode_for_model",
+        method!(RbTokenizer::encode_for_model, 8),
     )?;
+    class.define_method("_encode_fields", method!(RbTokenizer::encode_fields, 5))?;
+    class.define_method("_count_tokens", method!(RbTokenizer::count_tokens, 2))?;
+    class.define_method("_token_variants", method!(RbTokenizer::token_variants, 3))?;
+    class.define_method(
+        "_count_tokens_batch",
+        method!(RbTokenizer::count_tokens_batch, 3),
+    )?;
+    class.define_method("_truncate_text", method!(RbTokenizer::truncate_text, 3))?;
+    class.define_method("_chunk", method!(RbTokenizer::chunk, 4))?;
     class.define_method("_decode", method!(RbTokenizer::decode, 2))?;
-    class.define_method("_decode_batch", method!(RbTokenizer::decode_batch, 2))?;
+    class.define_method("_decode_batch", method!(RbTokenizer::decode_batch, 3))?;
     class.define_method("token_to_id", method!(RbTokenizer::token_to_id, 1))?;
     class.define_method("id_to_token", method!(RbTokenizer::id_to_token, 1))?;
+    class.define_method("token_bytes", method!(RbTokenizer::token_bytes, 1))?;
+    class.define_method("vocab_bytes", method!(RbTokenizer::vocab_bytes, 0))?;
+    class.define_method("prefix_index", method!(RbTokenizer::prefix_index, 0))?;
+    class.define_method("_heal", method!(RbTokenizer::heal, 2))?;
+    class.define_method(
+        "stop_sequence_matcher",
+        method!(RbTokenizer::stop_sequence_matcher, 1),
+    )?;
     class.define_method(
         "encode_special_tokens=",
         method!(RbTokenizer::set_encode_special_tokens, 1),
@@ -757,7 +2189,7 @@ pub fn init_tokenizer(ruby: &Ruby, module: &RModule) -> RbResult<()> {
         "add_special_tokens",
         method!(RbTokenizer::add_special_tokens, 1),
     )?;
-    class.define_method("train", method!(RbTokenizer::train, 2))?;
+    class.define_method("_train", method!(RbTokenizer::train, 4))?;
     class.define_method("model", method!(RbTokenizer::get_model, 0))?;
     class.define_method("model=", method!(RbTokenizer::set_model, 1))?;
     class.define_method("normalizer", method!(RbTokenizer::get_normalizer, 0))?;
@@ -777,6 +2209,8 @@ pub fn init_tokenizer(ruby: &Ruby, module: &RModule) -> RbResult<()> {
 
     let class = module.define_class("AddedToken", ruby.class_object())?;
     class.define_singleton_method("_new", function!(RbAddedToken::new, 2))?;
+    class.define_singleton_method("_load", function!(RbAddedToken::load, 1))?;
+    class.define_method("_dump", method!(RbAddedToken::dump, 1))?;
     class.define_method("content", method!(RbAddedToken::get_content, 0))?;
     class.define_method("rstrip", method!(RbAddedToken::get_rstrip, 0))?;
     class.define_method("lstrip", method!(RbAddedToken::get_lstrip, 0))?;
@@ -1,6 +1,9 @@
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, RwLock};
 
 use crate::models::RbModel;
+use crate::ruby::Interrupt;
 use crate::tokenizer::RbAddedToken;
 use magnus::prelude::*;
 use magnus::{
@@ -11,6 +14,7 @@ use serde::{Deserialize, Serialize};
 use tk::models::TrainerWrapper;
 use tk::Trainer;
 
+use super::utils::{marshal_dump, marshal_load};
 use super::{RbResult, TRAINERS};
 
 #[derive(DataTypeFunctions, Clone, Deserialize, Serialize)]
@@ -43,6 +47,120 @@ impl Trainer for RbTrainer {
     }
 }
 
+/// A training progress event: stage name, current and total count.
+pub(crate) type ProgressEvent = (&'static str, u64, u64);
+
+/// Sends progress events to the Ruby thread waiting on the training.
+/// Pre-processing is reported in bytes, at most once per percent.
+pub(crate) struct Progress {
+    sender: Mutex<Sender<ProgressEvent>>,
+    total: u64,
+    current: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(sender: Sender<ProgressEvent>, total: u64) -> Self {
+        Self {
+            sender: Mutex::new(sender),
+            total,
+            current: AtomicU64::new(0),
+        }
+    }
+
+    fn update(&self, stage: &'static str, current: u64, total: u64) {
+        // the receiver is gone if the Ruby thread stopped waiting
+        let _ = self.sender.lock().unwrap().send((stage, current, total));
+    }
+
+    fn advance(&self, bytes: u64) {
+        let previous = self.current.fetch_add(bytes, Ordering::Relaxed);
+        let current = (previous + bytes).min(self.total);
+        if self.total > 0 && previous * 100 / self.total != current * 100 / self.total {
+            self.update("pre_processing", current, self.total);
+        }
+    }
+}
+
+/// Wraps a trainer so feeding stops once the calling Ruby thread is
+/// interrupted, and reports progress as it goes: by bytes while feeding
+/// (`pre_processing`), and once before and after the final step (`training`). The model is only replaced
+/// once the final training step succeeds, so an interrupted run leaves it
+/// untouched.
+///
+/// Interrupts are checked between input sequences and before the final step.
+/// Tokenizers trainers have no hook in their merge or EM loop, so once that
+/// step starts, an interrupt takes effect when it finishes.
+pub(crate) struct ObservedTrainer {
+    trainer: RbTrainer,
+    interrupt: Arc<Interrupt>,
+    progress: Progress,
+    /// Special tokens from the final step, for the tokenizer being trained.
+    special_tokens: Mutex<Vec<tk::AddedToken>>,
+}
+
+impl ObservedTrainer {
+    pub fn new(trainer: RbTrainer, interrupt: Arc<Interrupt>, progress: Progress) -> Self {
+        Self {
+            trainer,
+            interrupt,
+            progress,
+            special_tokens: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn into_special_tokens(self) -> Vec<tk::AddedToken> {
+        self.special_tokens.into_inner().unwrap()
+    }
+}
+
+impl Trainer for ObservedTrainer {
+    type Model = RbModel;
+
+    fn should_show_progress(&self) -> bool {
+        self.trainer.should_show_progress()
+    }
+
+    fn train(&self, model: &mut RbModel) -> tk::Result<Vec<tk::AddedToken>> {
+        self.interrupt.check()?;
+        // the step has no hook for its iterations, so only its start and end are reported
+        self.progress.update("training", 0, 1);
+        let special_tokens = self.trainer.train(model)?;
+        self.progress.update("training", 1, 1);
+        *self.special_tokens.lock().unwrap() = special_tokens.clone();
+        Ok(special_tokens)
+    }
+
+    fn feed<I, S, F>(&mut self, iterator: I, process: F) -> tk::Result<()>
+    where
+        I: Iterator<Item = S> + Send,
+        S: AsRef<str> + Send,
+        F: Fn(&str) -> tk::Result<Vec<String>> + Sync,
+    {
+        let interrupt = &self.interrupt;
+        let progress = &self.progress;
+        self.trainer.feed(
+            iterator
+                .take_while(|_| interrupt.check().is_ok())
+                .inspect(|seq| progress.advance(seq.as_ref().len() as u64)),
+            |seq| {
+                interrupt.check()?;
+                process(seq)
+            },
+        )?;
+        interrupt.check()
+    }
+}
+
+impl RbTrainer {
+    pub fn dump(&self, _level: i32) -> RbResult<String> {
+        marshal_dump(self, "Trainer")
+    }
+
+    pub fn load(data: String) -> RbResult<Self> {
+        marshal_load(&data, "Trainer")
+    }
+}
+
 macro_rules! getter {
     ($self: ident, $variant: ident, $($name: tt)+) => {{
         if let TrainerWrapper::$variant(ref trainer) = *$self.trainer.read().unwrap() {
@@ -665,8 +783,9 @@ unsafe impl TypedData for RbTrainer {
     }
 
     fn data_type() -> &'static DataType {
-        static DATA_TYPE: DataType =
-            data_type_builder!(RbTrainer, "Tokenizers::Trainers::Trainer").build();
+        static DATA_TYPE: DataType = data_type_builder!(RbTrainer, "Tokenizers::Trainers::Trainer")
+            .frozen_shareable()
+            .build();
         &DATA_TYPE
     }
 
@@ -711,6 +830,8 @@ unsafe impl TypedData for RbTrainer {
 
 pub fn init_trainers(ruby: &Ruby, module: &RModule) -> RbResult<()> {
     let trainer = module.define_class("Trainer", ruby.class_object())?;
+    trainer.define_singleton_method("_load", function!(RbTrainer::load, 1))?;
+    trainer.define_method("_dump", method!(RbTrainer::dump, 1))?;
 
     let class = module.define_class("BpeTrainer", trainer)?;
     class.define_singleton_method("_new", function!(RbBpeTrainer::new, 1))?;
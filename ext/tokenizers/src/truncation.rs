@@ -0,0 +1,103 @@
+//! Truncation that keeps the start and the end of sequences and drops the
+//! tokens in between. Tokenizers only truncates one side, so sequences are
+//! encoded in full and cut here before post-processing.
+
+use std::ops::Range;
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use tk::Encoding;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct MiddleTruncation {
+    pub max_length: usize,
+    pub stride: usize,
+    pub head_ratio: f64,
+}
+
+impl MiddleTruncation {
+    /// Cuts the sequences so they fit `max_length` along with the
+    /// `added_tokens` of the post-processor. Like `longest_first`, the longest
+    /// sequence of a pair is cut first.
+    pub fn truncate(
+        &self,
+        encoding: Encoding,
+        pair: Option<Encoding>,
+        added_tokens: usize,
+    ) -> (Encoding, Option<Encoding>) {
+        let budget = self.max_length.saturating_sub(added_tokens);
+        let first = encoding.len();
+        let second = pair.as_ref().map_or(0, |p| p.len());
+        let (n1, n2) = if first + second <= budget {
+            (first, second)
+        } else {
+            let n1 = first.min((budget - budget / 2).max(budget.saturating_sub(second)));
+            (n1, (budget - n1).min(second))
+        };
+        (
+            self.cut(&encoding, n1, 0),
+            pair.map(|pair| self.cut(&pair, n2, 1)),
+        )
+    }
+
+    /// Keeps `length` tokens, split between the head and the tail. The removed
+    /// tokens, with `stride` tokens of context on each side, become the
+    /// overflowing encoding.
+    fn cut(&self, encoding: &Encoding, length: usize, type_id: u32) -> Encoding {
+        let len = encoding.len();
+        if len <= length {
+            return select_as(encoding, &[0..len], type_id);
+        }
+        let head = ((length as f64 * self.head_ratio).round() as usize).min(length);
+        let tail_start = len - (length - head);
+        let mut kept = select_as(encoding, &[0..head, tail_start..len], type_id);
+        let removed = head.saturating_sub(self.stride)..(tail_start + self.stride).min(len);
+        kept.set_overflowing(vec![select_as(encoding, &[removed], type_id)]);
+        kept
+    }
+}
+
+/// Copies the tokens in `ranges` to a new encoding. Offsets are kept as is,
+/// so they still point into the original input.
+pub fn select(encoding: &Encoding, ranges: &[Range<usize>]) -> Encoding {
+    let indices = || ranges.iter().flat_map(|range| range.clone());
+
+    // sequences are only tracked once there are special tokens or a pair
+    let sequence_ids = encoding.get_sequence_ids();
+    let mut sequence_ranges = AHashMap::new();
+    if encoding.n_sequences() > 1 || sequence_ids.contains(&None) {
+        for (i, index) in indices().enumerate() {
+            if let Some(sequence_id) = sequence_ids[index] {
+                sequence_ranges
+                    .entry(sequence_id)
+                    .and_modify(|range: &mut Range<usize>| range.end = i + 1)
+                    .or_insert(i..i + 1);
+            }
+        }
+    }
+
+    Encoding::new(
+        indices().map(|i| encoding.get_ids()[i]).collect(),
+        indices().map(|i| encoding.get_type_ids()[i]).collect(),
+        indices()
+            .map(|i| encoding.get_tokens()[i].clone())
+            .collect(),
+        indices().map(|i| encoding.get_word_ids()[i]).collect(),
+        indices().map(|i| encoding.get_offsets()[i]).collect(),
+        indices()
+            .map(|i| encoding.get_special_tokens_mask()[i])
+            .collect(),
+        indices()
+            .map(|i| encoding.get_attention_mask()[i])
+            .collect(),
+        vec![],
+        sequence_ranges,
+    )
+}
+
+/// Same as `select`, with `type_id` for all tokens.
+pub fn select_as(encoding: &Encoding, ranges: &[Range<usize>], type_id: u32) -> Encoding {
+    let mut selected = select(encoding, ranges);
+    selected.set_type_ids(vec![type_id; selected.len()]);
+    selected
+}
@@ -0,0 +1,20 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{RbError, RbResult};
+
+/// Data for `_dump`, so every class reports errors the same way.
+pub fn marshal_dump<T: Serialize + ?Sized>(value: &T, name: &str) -> RbResult<String> {
+    serde_json::to_string(value)
+        .map_err(|e| RbError::new_err(format!("Error while attempting to marshal {}: {}", name, e)))
+}
+
+/// Value from the data of `marshal_dump`, for `_load`.
+pub fn marshal_load<T: DeserializeOwned>(data: &str, name: &str) -> RbResult<T> {
+    serde_json::from_str(data).map_err(|e| {
+        RbError::new_err(format!(
+            "Error while attempting to unmarshal {}: {}",
+            name, e
+        ))
+    })
+}
@@ -1,5 +1,9 @@
+mod marshal;
+mod name;
 mod normalization;
 mod regex;
 
+pub use marshal::*;
+pub use name::*;
 pub use normalization::*;
 pub use regex::*;
@@ -0,0 +1,11 @@
+use magnus::{prelude::*, Symbol, TryConvert, Value};
+
+use crate::RbResult;
+
+/// Converts an option value given as a Symbol or a String.
+pub fn symbol_or_string(value: Value) -> RbResult<String> {
+    match Symbol::from_value(value) {
+        Some(symbol) => Ok(symbol.name()?.into_owned()),
+        None => String::try_convert(value),
+    }
+}
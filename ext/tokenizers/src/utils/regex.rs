@@ -2,7 +2,7 @@ use crate::{RbResult, TOKENIZERS};
 use magnus::{prelude::*, value::Lazy, Error, RClass, Ruby};
 use onig::Regex;
 
-#[magnus::wrap(class = "Tokenizers::Regex")]
+#[magnus::wrap(class = "Tokenizers::Regex", frozen_shareable)]
 pub struct RbRegex {
     pub inner: Regex,
     pub pattern: String,
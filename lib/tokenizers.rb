@@ -44,10 +44,15 @@ require_relative "tokenizers/trainers/word_piece_trainer"
 # other
 require_relative "tokenizers/added_token"
 require_relative "tokenizers/char_bpe_tokenizer"
+require_relative "tokenizers/check_frozen"
+require_relative "tokenizers/constraint"
 require_relative "tokenizers/encoding"
 require_relative "tokenizers/from_pretrained"
+require_relative "tokenizers/packed_batch"
+require_relative "tokenizers/token"
 require_relative "tokenizers/tokenizer"
 require_relative "tokenizers/version"
+require_relative "tokenizers/word"
 
 module Tokenizers
   class Error < StandardError; end
@@ -0,0 +1,26 @@
+module Tokenizers
+  # setters of components are defined in the extension, which doesn't check
+  # if objects are frozen, so frozen components would change without effect
+  module CheckFrozen
+    def self.prepend_to(klass)
+      setters = klass.instance_methods(false).select { |m| m.end_with?("=") && m != :== }
+      return if setters.empty?
+
+      # methods aren't defined with blocks, so they can be called from Ractors
+      mod = Module.new
+      setters.each do |name|
+        mod.module_eval <<~RUBY, __FILE__, __LINE__ + 1
+          def #{name}(value)
+            raise FrozenError.new("can't modify frozen #{klass.name}", receiver: self) if frozen?
+            super
+          end
+        RUBY
+      end
+      klass.prepend(mod)
+    end
+  end
+
+  [Decoders::Decoder, Models::Model, Normalizers::Normalizer, PreTokenizers::PreTokenizer, Processors::PostProcessor].each do |base|
+    [base, *base.subclasses].each { |klass| CheckFrozen.prepend_to(klass) }
+  end
+end
@@ -0,0 +1,186 @@
+module Tokenizers
+  class Constraint
+    WHITESPACE = "[ ]?"
+    STRING_CHAR = '([^"\\\\\x00-\x1F\x7F]|\\\\["\\\\/bfnrt]|\\\\u[0-9a-fA-F]{4})'
+    INTEGER = '-?(0|[1-9][0-9]*)'
+    NUMBER = '-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?'
+    FORMATS = {
+      "date" => '[0-9]{4}-[0-9]{2}-[0-9]{2}',
+      "time" => '[0-9]{2}:[0-9]{2}:[0-9]{2}(\.[0-9]+)?(Z|[+-][0-9]{2}:[0-9]{2})?',
+      "date-time" => '[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}(\.[0-9]+)?(Z|[+-][0-9]{2}:[0-9]{2})?',
+      "uuid" => '[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}'
+    }
+    KEYWORDS = ["type", "const", "enum", "anyOf", "oneOf", "allOf", "title", "description", "default", "examples", "$schema", "$id", "$comment"]
+    TYPE_KEYWORDS = {
+      "string" => ["pattern", "format", "minLength", "maxLength"],
+      "array" => ["items", "minItems", "maxItems"],
+      "object" => ["properties", "required"]
+    }
+
+    def self.regex(pattern, tokenizer)
+      _regex(pattern, tokenizer)
+    end
+
+    def self.json_schema(schema, tokenizer)
+      regex(json_schema_regex(schema), tokenizer)
+    end
+
+    def self.json_schema_regex(schema)
+      require "json"
+
+      schema = JSON.parse(schema.is_a?(String) ? schema : JSON.generate(schema))
+      schema_regex(schema)
+    end
+
+    def initial_state
+      0
+    end
+
+    class << self
+      private
+
+      def schema_regex(schema)
+        raise ArgumentError, "Schema must be an object" unless schema.is_a?(Hash)
+
+        # raise instead of ignoring keywords that would allow other values
+        types = Array(schema["type"])
+        types << "object" if schema.key?("properties")
+        types << "array" if schema.key?("items")
+        supported = KEYWORDS + types.flat_map { |t| TYPE_KEYWORDS.fetch(t, []) }
+        unsupported = schema.keys - supported
+        raise ArgumentError, "Unsupported schema keyword: #{unsupported.first}" if unsupported.any?
+
+        if schema.key?("const")
+          return literal(schema["const"])
+        end
+
+        if schema.key?("enum")
+          return alternation(schema["enum"].map { |v| literal(v) })
+        end
+
+        ["anyOf", "oneOf"].each do |keyword|
+          if schema.key?(keyword)
+            return alternation(schema[keyword].map { |s| schema_regex(s) })
+          end
+        end
+
+        if schema.key?("allOf")
+          raise ArgumentError, "Unsupported schema keyword: allOf" unless schema["allOf"].size == 1
+          return schema_regex(schema["allOf"].first)
+        end
+
+        type = schema["type"]
+        type ||= "object" if schema.key?("properties")
+        type ||= "array" if schema.key?("items")
+        raise ArgumentError, "Schema must have a type" unless type
+
+        if type.is_a?(Array)
+          alternation(type.map { |t| type_regex(t, schema) })
+        else
+          type_regex(type, schema)
+        end
+      end
+
+      def type_regex(type, schema)
+        case type
+        when "string"
+          string_regex(schema)
+        when "integer"
+          INTEGER
+        when "number"
+          NUMBER
+        when "boolean"
+          "(true|false)"
+        when "null"
+          "null"
+        when "array"
+          array_regex(schema)
+        when "object"
+          object_regex(schema)
+        else
+          raise ArgumentError, "Unsupported type: #{type}"
+        end
+      end
+
+      def string_regex(schema)
+        if schema["pattern"]
+          pattern = schema["pattern"].delete_prefix("^").delete_suffix("$")
+          return "\"(#{_json_string_regex(pattern)})\""
+        end
+
+        if schema["format"]
+          format = FORMATS[schema["format"]]
+          raise ArgumentError, "Unsupported format: #{schema["format"]}" unless format
+          return "\"#{format}\""
+        end
+
+        "\"#{STRING_CHAR}#{repetition(schema["minLength"] || 0, schema["maxLength"])}\""
+      end
+
+      def array_regex(schema)
+        raise ArgumentError, "Arrays must have items" unless schema["items"]
+
+        item = schema_regex(schema["items"])
+        min_items = schema["minItems"] || 0
+        max_items = schema["maxItems"]
+        raise ArgumentError, "maxItems must be at least minItems" if max_items && max_items < min_items
+
+        more = "(#{WHITESPACE},#{WHITESPACE}#{item})"
+        items =
+          if max_items == 0
+            ""
+          elsif min_items == 0
+            "(#{item}#{more}#{repetition(0, max_items && max_items - 1)})?"
+          else
+            "#{item}#{more}#{repetition(min_items - 1, max_items && max_items - 1)}"
+          end
+        "\\[#{WHITESPACE}#{items}#{WHITESPACE}\\]"
+      end
+
+      def object_regex(schema)
+        properties = schema["properties"] || {}
+        required = schema["required"] || []
+        return "\\{#{WHITESPACE}\\}" if properties.empty?
+
+        members =
+          properties.map do |name, property|
+            ["#{literal(name)}#{WHITESPACE}:#{WHITESPACE}#{schema_regex(property)}", required.include?(name)]
+          end
+
+        # optional members can't leave a dangling comma, so there's an
+        # alternative for each member that can come first
+        separator = "#{WHITESPACE},#{WHITESPACE}"
+        alternatives = []
+        members.each_with_index do |(member, member_required), i|
+          rest =
+            members[(i + 1)..].map do |other, other_required|
+              other_required ? "#{separator}#{other}" : "(#{separator}#{other})?"
+            end
+          alternatives << member + rest.join
+          break if member_required
+        end
+        body = alternation(alternatives)
+        body = "(#{body})?" if members.none? { |_, r| r }
+        "\\{#{WHITESPACE}#{body}#{WHITESPACE}\\}"
+      end
+
+      def repetition(min, max)
+        if max
+          "{#{min},#{max}}"
+        elsif min == 0
+          "*"
+        else
+          "{#{min},}"
+        end
+      end
+
+      def alternation(regexes)
+        regexes.size == 1 ? regexes.first : "(#{regexes.join("|")})"
+      end
+
+      def literal(value)
+        JSON.generate(value).gsub(/[\\.+*?()|\[\]{}^$#&\-~]/) { |c| "\\#{c}" }
+      end
+    end
+  end
+end
@@ -1,5 +1,32 @@
 module Tokenizers
   class Encoding
+    def self.merge(encodings, growing_offsets: true)
+      _merge(encodings, growing_offsets)
+    end
+
+    def pad(length, direction: :right, pad_id: 0, pad_type_id: 0, pad_token: "[PAD]")
+      _pad(length, direction, pad_id, pad_type_id, pad_token)
+    end
+
+    def truncate(max_length, stride: 0, direction: :right)
+      _truncate(max_length, stride, direction)
+    end
+
+    def each_token(&block)
+      return enum_for(:each_token) unless block_given?
+
+      to_a.each(&block)
+      self
+    end
+
+    def original_text(sequence_index = 0)
+      _original_text(sequence_index)
+    end
+
+    def normalized_text(sequence_index = 0)
+      _normalized_text(sequence_index)
+    end
+
     def word_to_tokens(word_index, sequence_index = 0)
       _word_to_tokens(word_index, sequence_index)
     end
@@ -15,5 +42,9 @@ module Tokenizers
     def char_to_word(char_pos, sequence_index = 0)
       _char_to_word(word_index, sequence_index)
     end
+
+    def to_numo(name = :ids, dtype: :int64)
+      PackedBatch.numo_class(dtype).from_binary(_pack(name, dtype))
+    end
   end
 end
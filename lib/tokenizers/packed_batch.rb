@@ -0,0 +1,36 @@
+module Tokenizers
+  class PackedBatch
+    attr_reader :dtype, :shape, :ids, :attention_mask, :type_ids, :special_tokens_mask
+
+    # @private
+    def initialize(dtype, shape, ids, attention_mask, type_ids, special_tokens_mask)
+      @dtype = dtype
+      @shape = shape.freeze
+      @ids = ids
+      @attention_mask = attention_mask
+      @type_ids = type_ids
+      @special_tokens_mask = special_tokens_mask
+      freeze
+    end
+
+    def to_numo(name = :ids)
+      PackedBatch.numo_class(dtype).from_binary(public_send(name), shape)
+    end
+
+    # @private
+    def self.numo_class(dtype)
+      raise Error, "Install the numo-narray gem to use Numo" unless defined?(::Numo::NArray)
+
+      case dtype.to_s
+      when "int32"
+        ::Numo::Int32
+      when "int64"
+        ::Numo::Int64
+      when "uint16"
+        ::Numo::UInt16
+      else
+        raise ArgumentError, "The dtype value must be 'int32', 'int64', or 'uint16'"
+      end
+    end
+  end
+end
@@ -0,0 +1,7 @@
+module Tokenizers
+  Token = Data.define(:id, :text, :offsets, :word_id, :sequence_id, :type_id, :special) do
+    def special?
+      special
+    end
+  end
+end
@@ -10,24 +10,104 @@ module Tokenizers
       _save(path, pretty)
     end
 
-    def encode(sequence, pair = nil, is_pretokenized: false, add_special_tokens: true)
-      _encode(sequence, pair, is_pretokenized, add_special_tokens)
+    def encode(sequence, pair = nil, is_pretokenized: false, add_special_tokens: true, truncation: nil, padding: nil, offsets: :char, keep_text: false)
+      _encode(sequence, pair, is_pretokenized, add_special_tokens, truncation, padding, offsets, keep_text)
     end
 
-    def encode_batch(input, is_pretokenized: false, add_special_tokens: true)
-      _encode_batch(input, is_pretokenized, add_special_tokens)
+    def encode_batch(input, is_pretokenized: false, add_special_tokens: true, threads: nil, truncation: nil, padding: nil, offsets: :char, keep_text: false)
+      _encode_batch(input, is_pretokenized, add_special_tokens, threads, truncation, padding, offsets, keep_text)
     end
 
-    def encode_batch_fast(input, is_pretokenized: false, add_special_tokens: true)
-      _encode_batch_fast(input, is_pretokenized, add_special_tokens)
+    def encode_batch_fast(input, is_pretokenized: false, add_special_tokens: true, threads: nil, truncation: nil, padding: nil)
+      _encode_batch_fast(input, is_pretokenized, add_special_tokens, threads, truncation, padding)
+    end
+
+    def encode_batch_packed(input, dtype: :int64, is_pretokenized: false, add_special_tokens: true, threads: nil, truncation: nil, padding: nil)
+      PackedBatch.new(*_encode_batch_packed(input, dtype, is_pretokenized, add_special_tokens, threads, truncation, padding))
+    end
+
+    def encode_batch_numo(input, dtype: :int64, **options)
+      batch = encode_batch_packed(input, dtype: dtype, **options)
+      [:ids, :attention_mask, :type_ids, :special_tokens_mask].to_h { |name| [name, batch.to_numo(name)] }
+    end
+
+    def encode_for_model(input, return: [:input_ids, :token_type_ids, :attention_mask], dtype: nil, is_pretokenized: false, add_special_tokens: true, threads: nil, truncation: nil, padding: nil)
+      _encode_for_model(input, binding.local_variable_get(:return), dtype, is_pretokenized, add_special_tokens, threads, truncation, padding)
+    end
+
+    def encode_fields(fields, max_length:, priorities: nil, template: nil)
+      _encode_fields(fields.keys.map(&:to_s), fields.values, max_length, priorities&.transform_keys(&:to_s), template)
+    end
+
+    def count_tokens(text, add_special_tokens: true)
+      _count_tokens(text, add_special_tokens)
+    end
+
+    def count_tokens_batch(texts, add_special_tokens: true, threads: nil)
+      _count_tokens_batch(texts, add_special_tokens, threads)
+    end
+
+    def token_variants(word, casing: true, spacing: true)
+      _token_variants(word, casing, spacing)
+    end
+
+    # overhead is the number of tokens the chat template adds to each message
+    def fit_messages(messages, max_tokens:, overhead: 0, reserve: 0, keep: [:system, :last])
+      budget = max_tokens - reserve
+      contents = messages.map { |m| (m[:content] || m["content"]).to_s }
+      counts = count_tokens_batch(contents, add_special_tokens: false).map { |c| c + overhead }
+
+      kept = Array.new(messages.size, false)
+      keep.each do |value|
+        case value.to_sym
+        when :system
+          messages.each_with_index do |m, i|
+            kept[i] = true if (m[:role] || m["role"]).to_s == "system"
+          end
+        when :first
+          kept[0] = true if messages.any?
+        when :last
+          last = messages.rindex { |m| (m[:role] || m["role"]).to_s == "user" }
+          kept[last] = true if last
+        else
+          raise ArgumentError, "The keep values must be 'system', 'first', or 'last'"
+        end
+      end
+
+      num_tokens = counts.each_with_index.sum { |c, i| kept[i] ? c : 0 }
+      if num_tokens > budget
+        raise Error, "Kept messages have #{num_tokens} tokens, which exceeds the budget of #{budget}"
+      end
+
+      # drop the oldest messages first
+      (messages.size - 1).downto(0) do |i|
+        next if kept[i]
+        break if num_tokens + counts[i] > budget
+        kept[i] = true
+        num_tokens += counts[i]
+      end
+
+      {messages: messages.select.with_index { |_, i| kept[i] }, num_tokens: num_tokens}
+    end
+
+    def truncate_text(text, max_tokens, side: :right)
+      _truncate_text(text, max_tokens, side)
+    end
+
+    def chunk(text, max_tokens:, overlap: 0, boundaries: [:paragraph, :sentence, :word])
+      _chunk(text, max_tokens, overlap, boundaries)
     end
 
     def decode(ids, skip_special_tokens: true)
       _decode(ids, skip_special_tokens)
     end
 
-    def decode_batch(sequences, skip_special_tokens: true)
-      _decode_batch(sequences, skip_special_tokens)
+    def decode_batch(sequences, skip_special_tokens: true, threads: nil)
+      _decode_batch(sequences, skip_special_tokens, threads)
+    end
+
+    def train(files, trainer = nil, progress: nil, threads: nil)
+      _train(files, trainer, progress, threads)
     end
 
     def enable_padding(**options)
@@ -45,5 +125,26 @@ module Tokenizers
     def vocab_size(with_added_tokens: true)
       _vocab_size(with_added_tokens)
     end
+
+    def heal(ids, index: prefix_index)
+      _heal(ids, index)
+    end
+
+    def freeze
+      _freeze
+      super
+    end
+
+    def make_shareable
+      Ractor.make_shareable(freeze)
+    end
+
+    def _dump(level)
+      to_s
+    end
+
+    def self._load(str)
+      from_str(str)
+    end
   end
 end
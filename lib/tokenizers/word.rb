@@ -0,0 +1,3 @@
+module Tokenizers
+  Word = Data.define(:id, :sequence_id, :tokens, :offsets)
+end
@@ -10,4 +10,14 @@ class AddedTokenTest < Minitest::Test
     assert_equal true, token.normalized
     assert_equal false, token.special
   end
+
+  def test_marshal
+    token = Tokenizers::AddedToken.new("[MASK]", lstrip: true, special: true)
+    new_token = Marshal.load(Marshal.dump(token))
+    assert_instance_of Tokenizers::AddedToken, new_token
+    assert_equal "[MASK]", new_token.content
+    assert_equal true, new_token.lstrip
+    assert_equal false, new_token.rstrip
+    assert_equal true, new_token.special
+  end
 end
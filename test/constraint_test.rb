@@ -0,0 +1,94 @@
+require_relative "test_helper"
+
+class ConstraintTest < Minitest::Test
+  def test_regex
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    constraint = Tokenizers::Constraint.regex("[0-9]{3}", tokenizer)
+
+    state = constraint.initial_state
+    ids = constraint.allowed_token_ids(state)
+    assert_includes ids, tokenizer.token_to_id("123")
+    refute_includes ids, tokenizer.token_to_id("a")
+    refute constraint.final?(state)
+
+    state = constraint.advance(state, tokenizer.token_to_id("12"))
+    refute constraint.final?(state)
+    assert_nil constraint.advance(state, tokenizer.token_to_id("12"))
+
+    state = constraint.advance(state, tokenizer.token_to_id("3"))
+    assert constraint.final?(state)
+    assert_empty constraint.allowed_token_ids(state)
+  end
+
+  def test_regex_invalid
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    assert_raises(ArgumentError) do
+      Tokenizers::Constraint.regex("[0-9", tokenizer)
+    end
+
+    constraint = Tokenizers::Constraint.regex("a", tokenizer)
+    error = assert_raises(ArgumentError) do
+      constraint.allowed_token_ids(constraint.num_states)
+    end
+    assert_equal "Invalid state", error.message
+  end
+
+  def test_json_schema
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    constraint = Tokenizers::Constraint.json_schema({type: "boolean"}, tokenizer)
+
+    state = constraint.initial_state
+    assert_includes constraint.allowed_token_ids(state), tokenizer.token_to_id("true")
+    state = constraint.advance(state, tokenizer.token_to_id("false"))
+    assert constraint.final?(state)
+  end
+
+  def test_json_schema_regex
+    schema = {type: "array", items: {type: "integer"}, maxItems: 3}
+    assert_equal '\[[ ]?(-?(0|[1-9][0-9]*)([ ]?,[ ]?-?(0|[1-9][0-9]*)){0,2})?[ ]?\]', Tokenizers::Constraint.json_schema_regex(schema)
+
+    assert_equal '("red"|"green")', Tokenizers::Constraint.json_schema_regex({enum: ["red", "green"]})
+    assert_equal '\{[ ]?"a"[ ]?:[ ]?null([ ]?,[ ]?"b"[ ]?:[ ]?(true|false))?[ ]?\}', Tokenizers::Constraint.json_schema_regex('{"properties": {"a": {"type": "null"}, "b": {"type": "boolean"}}, "required": ["a"]}')
+
+    error = assert_raises(ArgumentError) do
+      Tokenizers::Constraint.json_schema_regex({"$ref" => "#/definitions/a"})
+    end
+    assert_equal "Unsupported schema keyword: $ref", error.message
+
+    error = assert_raises(ArgumentError) do
+      Tokenizers::Constraint.json_schema_regex({type: "integer", minimum: 0})
+    end
+    assert_equal "Unsupported schema keyword: minimum", error.message
+
+    error = assert_raises(ArgumentError) do
+      Tokenizers::Constraint.json_schema_regex({type: "object", properties: {}, additionalProperties: false})
+    end
+    assert_equal "Unsupported schema keyword: additionalProperties", error.message
+
+    assert_equal '"(a)"', Tokenizers::Constraint.json_schema_regex({type: "string", pattern: "^a$", description: "letter"})
+  end
+
+  def test_json_schema_pattern
+    # characters that JSON escapes are matched in escaped form
+    assert_equal '"((?:a\\\\"))"', Tokenizers::Constraint.json_schema_regex({type: "string", pattern: 'a"'})
+
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    constraint = Tokenizers::Constraint.json_schema({type: "string", pattern: "^.$"}, tokenizer)
+    quote = tokenizer.token_to_id("\"")
+    state = constraint.advance(constraint.initial_state, quote)
+    assert_nil constraint.advance(state, quote)
+    state = constraint.advance(state, tokenizer.token_to_id("\\"))
+    state = constraint.advance(state, quote)
+    refute constraint.final?(state)
+    state = constraint.advance(state, quote)
+    assert constraint.final?(state)
+  end
+
+  def test_special_tokens
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    constraint = Tokenizers::Constraint.regex(".*", tokenizer)
+    ids = constraint.allowed_token_ids(constraint.initial_state)
+    assert_includes ids, tokenizer.token_to_id("hello")
+    refute_includes ids, tokenizer.token_to_id("<|endoftext|>")
+  end
+end
@@ -126,4 +126,12 @@ class DecoderTest < Minitest::Test
     decoder.cleanup = true
     assert_equal true, decoder.cleanup
   end
+
+  def test_marshal
+    decoder = Tokenizers::Decoders::Metaspace.new(replacement: "_", prepend_scheme: "first")
+    new_decoder = Marshal.load(Marshal.dump(decoder))
+    assert_instance_of Tokenizers::Decoders::Metaspace, new_decoder
+    assert_equal "_", new_decoder.replacement
+    assert_equal "first", new_decoder.prepend_scheme
+  end
 end
@@ -0,0 +1,179 @@
+require_relative "test_helper"
+
+class EncodingTest < Minitest::Test
+  def test_merge
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    query = tokenizer.encode("I can", add_special_tokens: false)
+    passage = tokenizer.encode("feel the magic", add_special_tokens: false)
+
+    merged = Tokenizers::Encoding.merge([query, passage])
+    assert_equal ["I", "can", "feel", "the", "magic"], merged.tokens
+    assert_equal [[0, 1], [2, 5], [5, 9], [10, 13], [14, 19]], merged.offsets
+
+    merged = Tokenizers::Encoding.merge([query, passage], growing_offsets: false)
+    assert_equal [[0, 1], [2, 5], [0, 4], [5, 8], [9, 14]], merged.offsets
+  end
+
+  def test_pad
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("hello")
+
+    padded = encoding.pad(5)
+    assert_equal ["[CLS]", "hello", "[SEP]", "[PAD]", "[PAD]"], padded.tokens
+    assert_equal [1, 1, 1, 0, 0], padded.attention_mask
+    assert_equal 3, encoding.ids.size
+
+    padded = encoding.pad(4, direction: :left, pad_id: 5, pad_token: "<pad>")
+    assert_equal ["<pad>", "[CLS]", "hello", "[SEP]"], padded.tokens
+    assert_equal 5, padded.ids.first
+
+    assert_raises(ArgumentError) do
+      encoding.pad(5, direction: :middle)
+    end
+  end
+
+  def test_truncate
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can feel the magic, can you?", add_special_tokens: false)
+
+    truncated = encoding.truncate(4)
+    assert_equal ["I", "can", "feel", "the"], truncated.tokens
+    assert_equal [["magic", ",", "can", "you"], ["?"]], truncated.overflowing.map(&:tokens)
+    assert_equal 9, encoding.ids.size
+
+    truncated = encoding.truncate(4, stride: 1)
+    assert_equal [["the", "magic", ",", "can"], ["can", "you", "?"]], truncated.overflowing.map(&:tokens)
+
+    truncated = encoding.truncate(4, direction: :left)
+    assert_equal [",", "can", "you", "?"], truncated.tokens
+
+    assert_raises(ArgumentError) do
+      encoding.truncate(4, stride: 4)
+    end
+  end
+
+  def test_slice
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can feel the magic")
+
+    sliced = encoding.slice(1..2)
+    assert_equal ["I", "can"], sliced.tokens
+    assert_equal [[0, 1], [2, 5]], sliced.offsets
+    assert_equal [0, 0], sliced.sequence_ids
+
+    sliced = encoding.slice(-2..)
+    assert_equal ["magic", "[SEP]"], sliced.tokens
+    assert_equal [0, nil], sliced.sequence_ids
+
+    assert_raises(RangeError) do
+      encoding.slice(10..12)
+    end
+  end
+
+  def test_each_token
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can", "feel")
+
+    tokens = encoding.each_token.to_a
+    assert_equal 6, tokens.size
+    assert_equal ["[CLS]", "I", "can", "[SEP]", "feel", "[SEP]"], tokens.map(&:text)
+
+    token = tokens[4]
+    assert_equal encoding.ids[4], token.id
+    assert_equal [0, 4], token.offsets
+    assert_equal 0, token.word_id
+    assert_equal 1, token.sequence_id
+    assert_equal 1, token.type_id
+    refute token.special?
+    assert tokens[0].special?
+    assert_nil tokens[0].sequence_id
+
+    texts = []
+    assert_same encoding, encoding.each_token { |t| texts << t.text }
+    assert_equal tokens.map(&:text), texts
+    assert_equal tokens, encoding.to_a
+  end
+
+  def test_to_h
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can")
+
+    hash = encoding.to_h
+    assert_equal encoding.ids, hash[:ids]
+    assert_equal ["[CLS]", "I", "can", "[SEP]"], hash[:tokens]
+    assert_equal [nil, 0, 0, nil], hash[:sequence_ids]
+    assert_equal [1, 0, 0, 1], hash[:special_tokens_mask]
+    assert_equal [1, 1, 1, 1], hash[:attention_mask]
+  end
+
+  def test_truncated
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can feel the magic, can you?", truncation: 5)
+    assert encoding.truncated?
+    assert_equal 11, encoding.original_length
+    assert_equal 2, encoding.num_overflowing
+    assert_equal 5, encoding.ids.size
+
+    copy = Marshal.load(Marshal.dump(encoding))
+    assert copy.truncated?
+    assert_equal 11, copy.original_length
+    assert_equal encoding.ids, copy.ids
+
+    encoding = tokenizer.encode("I can feel the magic, can you?", padding: {length: 16})
+    refute encoding.truncated?
+    assert_equal 11, encoding.original_length
+    assert_equal 0, encoding.num_overflowing
+
+    encodings = tokenizer.encode_batch(["I can", "I can feel the magic, can you?"], truncation: 5)
+    assert_equal [4, 11], encodings.map(&:original_length)
+    assert_equal [false, true], encodings.map(&:truncated?)
+  end
+
+  def test_keep_text
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can feel", "the magic", keep_text: true)
+    assert_equal "I can feel", encoding.original_text
+    assert_equal "the magic", encoding.original_text(1)
+    assert_equal "I can feel", encoding.normalized_text
+    assert_nil encoding.original_text(2)
+
+    copy = Marshal.load(Marshal.dump(encoding))
+    assert_equal "the magic", copy.original_text(1)
+    assert_equal "I can feel", copy.normalized_text
+
+    encoding = tokenizer.encode("I can feel")
+    assert_nil encoding.original_text
+    assert_nil encoding.normalized_text
+
+    encodings = tokenizer.encode_batch(["I can", "feel"], keep_text: true)
+    assert_equal ["I can", "feel"], encodings.map(&:original_text)
+  end
+
+  def test_words
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can", "the magic")
+
+    words = encoding.words
+    assert_equal [0, 1, 0, 1], words.map(&:id)
+    assert_equal [0, 0, 1, 1], words.map(&:sequence_id)
+    assert_equal [1...2, 2...3, 4...5, 5...6], words.map(&:tokens)
+    assert_equal [[0, 1], [2, 5], [0, 3], [4, 9]], words.map(&:offsets)
+    assert_equal ["magic"], encoding.tokens[words.last.tokens]
+  end
+
+  def test_sequence
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    encoding = tokenizer.encode("I can", "the magic", keep_text: true)
+
+    sequence = encoding.sequence(1)
+    assert_equal ["the", "magic"], sequence.tokens
+    assert_equal [1, 1], sequence.type_ids
+    assert_equal [0, 0], sequence.sequence_ids
+    assert_equal [[0, 3], [4, 9]], sequence.offsets
+    assert_equal "the magic", sequence.original_text
+    assert_equal 1, sequence.char_to_token(4)
+
+    assert_equal ["I", "can"], encoding.sequence(0).tokens
+    assert_nil encoding.sequence(2)
+  end
+end
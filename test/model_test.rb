@@ -91,4 +91,14 @@ class ModelTest < Minitest::Test
 
     Tokenizers::Models::Unigram.new(vocab: [["a", 0.117], ["b", 0.786]])
   end
+
+  def test_marshal
+    model = Tokenizers::Models::BPE.new(vocab: {"a" => 0, "b" => 1, "ab" => 2}, merges: [["a", "b"]], unk_token: "[UNK]")
+    new_model = Marshal.load(Marshal.dump(model))
+    assert_instance_of Tokenizers::Models::BPE, new_model
+    assert_equal "[UNK]", new_model.unk_token
+
+    tokenizer = Tokenizers::Tokenizer.new(new_model)
+    assert_equal [2], tokenizer.encode("ab").ids
+  end
 end
@@ -109,4 +109,11 @@ class NormalizerTest < Minitest::Test
     assert_instance_of Tokenizers::Normalizers::StripAccents, normalizer
     assert_kind_of Tokenizers::Normalizers::StripAccents, normalizer
   end
+
+  def test_marshal
+    normalizer = Tokenizers::Normalizers::Sequence.new([Tokenizers::Normalizers::NFD.new, Tokenizers::Normalizers::StripAccents.new])
+    new_normalizer = Marshal.load(Marshal.dump(normalizer))
+    assert_instance_of Tokenizers::Normalizers::Sequence, new_normalizer
+    assert_equal "Hello how are u?", new_normalizer.normalize_str("Héllò hôw are ü?")
+  end
 end
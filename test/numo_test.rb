@@ -0,0 +1,33 @@
+require_relative "test_helper"
+
+class NumoTest < Minitest::Test
+  def test_encode_batch_numo
+    inputs = tokenizer.encode_batch_numo(["hello", "I can feel the magic"])
+    ids = inputs[:ids]
+    assert_kind_of Numo::Int64, ids
+    assert_equal [2, 7], ids.shape
+    assert_equal [101, 19082, 102, 0, 0, 0, 0], ids[0, true].to_a
+    assert_equal [1, 1, 1, 0, 0, 0, 0], inputs[:attention_mask][0, true].to_a
+    assert_equal [[0] * 7] * 2, inputs[:type_ids].to_a
+
+    inputs = tokenizer.encode_batch_numo(["hello"], dtype: :int32)
+    assert_kind_of Numo::Int32, inputs[:ids]
+  end
+
+  def test_packed_batch
+    batch = tokenizer.encode_batch_packed(["hello"], dtype: :uint16)
+    assert_kind_of Numo::UInt16, batch.to_numo
+    assert_equal [[1, 1, 1]], batch.to_numo(:attention_mask).to_a
+  end
+
+  def test_encoding
+    encoding = tokenizer.encode("hello")
+    assert_kind_of Numo::Int64, encoding.to_numo
+    assert_equal [101, 19082, 102], encoding.to_numo.to_a
+    assert_equal [1, 1, 1], encoding.to_numo(:attention_mask, dtype: :int32).to_a
+  end
+
+  def tokenizer
+    @tokenizer ||= Tokenizers.from_pretrained("bert-base-cased")
+  end
+end
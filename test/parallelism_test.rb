@@ -0,0 +1,86 @@
+require_relative "test_helper"
+
+class ParallelismTest < Minitest::Test
+  def teardown
+    Tokenizers.parallelism = true
+    Tokenizers.num_threads = nil
+    super
+  end
+
+  def test_parallelism
+    Tokenizers.parallelism = false
+    assert_equal false, Tokenizers.parallelism
+    assert_equal [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102], tokenizer.encode_batch(["I can feel the magic, can you?"] * 10).last.ids
+
+    Tokenizers.parallelism = true
+    assert_equal true, Tokenizers.parallelism
+  end
+
+  def test_num_threads
+    Tokenizers.num_threads = 2
+    assert_equal 2, Tokenizers.num_threads
+    assert_equal 10, tokenizer.encode_batch(["I can feel the magic, can you?"] * 10).size
+
+    Tokenizers.num_threads = nil
+    assert_operator Tokenizers.num_threads, :>=, 1
+
+    error = assert_raises(ArgumentError) do
+      Tokenizers.num_threads = 0
+    end
+    assert_equal "threads must be greater than 0", error.message
+  end
+
+  def test_threads
+    encodings = tokenizer.encode_batch(["I can feel the magic, can you?"] * 10, threads: 2)
+    assert_equal ["I can feel the magic, can you?"] * 10, tokenizer.decode_batch(encodings.map(&:ids), threads: 3)
+    assert_equal 10, tokenizer.encode_batch_fast(["I can feel the magic, can you?"] * 10, threads: 1).size
+
+    assert_raises(ArgumentError) do
+      tokenizer.encode_batch(["hello"], threads: 0)
+    end
+  end
+
+  def test_fork
+    skip unless Process.respond_to?(:fork)
+
+    tokenizer.encode_batch(["I can feel the magic, can you?"] * 100)
+    tokenizer.encode_batch(["I can feel the magic, can you?"] * 100, threads: 2)
+
+    reader, writer = IO.pipe
+    pid =
+      fork do
+        reader.close
+        a = tokenizer.encode_batch(["I can feel the magic, can you?"] * 100).size
+        b = tokenizer.encode_batch(["I can feel the magic, can you?"] * 100, threads: 2).size
+        writer.write("#{a} #{b}")
+        writer.close
+        exit!(0)
+      end
+    writer.close
+    Process.wait(pid)
+    assert_equal "100 100", reader.read
+  end
+
+  def test_fork_after_num_threads
+    skip unless Process.respond_to?(:fork)
+
+    # starts the global pool
+    assert_operator Tokenizers.num_threads, :>=, 1
+
+    reader, writer = IO.pipe
+    pid =
+      fork do
+        reader.close
+        writer.write(tokenizer.encode_batch(["I can feel the magic, can you?"] * 100).size.to_s)
+        writer.close
+        exit!(0)
+      end
+    writer.close
+    Process.wait(pid)
+    assert_equal "100", reader.read
+  end
+
+  def tokenizer
+    @tokenizer ||= Tokenizers.from_pretrained("bert-base-cased")
+  end
+end
@@ -111,4 +111,11 @@ class PreTokenizerTest < Minitest::Test
     assert_instance_of Tokenizers::PreTokenizers::WhitespaceSplit, pre_tokenizer
     assert_kind_of Tokenizers::PreTokenizers::PreTokenizer, pre_tokenizer
   end
+
+  def test_marshal
+    pre_tokenizer = Tokenizers::PreTokenizers::ByteLevel.new(add_prefix_space: false)
+    new_pre_tokenizer = Marshal.load(Marshal.dump(pre_tokenizer))
+    assert_instance_of Tokenizers::PreTokenizers::ByteLevel, new_pre_tokenizer
+    assert_equal false, new_pre_tokenizer.add_prefix_space
+  end
 end
@@ -42,4 +42,10 @@ class ProcessorTest < Minitest::Test
       ]
     )
   end
+
+  def test_marshal
+    processor = Tokenizers::Processors::RobertaProcessing.new(["</s>", 2], ["<s>", 0])
+    new_processor = Marshal.load(Marshal.dump(processor))
+    assert_instance_of Tokenizers::Processors::RobertaProcessing, new_processor
+  end
 end
@@ -1,4 +1,5 @@
 require_relative "test_helper"
+require "tempfile"
 
 class TokenizerTest < Minitest::Test
   def test_from_pretrained_bert
@@ -39,6 +40,36 @@ class TokenizerTest < Minitest::Test
     assert_equal "Mythological creatures like the mighty gryphon inspire awe!", tokenizer.decode(encoded.ids)
   end
 
+  def test_encode_offsets
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    text = "😁 I can feel the magic"
+
+    encoded = tokenizer.encode(text)
+    assert_equal [17, 22], encoded.offsets[6]
+    assert_equal "magic", text[17...22]
+    assert_equal 6, encoded.char_to_token(17)
+
+    encoded = tokenizer.encode(text, offsets: :byte)
+    assert_equal [20, 25], encoded.offsets[6]
+    assert_equal "magic", text.byteslice(20...25)
+    assert_equal 6, encoded.char_to_token(20)
+    assert_equal [20, 25], encoded.token_to_chars(6)
+
+    encoded = tokenizer.encode(text, "😁 you?", offsets: :utf16)
+    assert_equal [18, 23], encoded.offsets[6]
+    assert_equal "magic", text.encode("UTF-16LE").byteslice(36...46).encode("UTF-8")
+    assert_equal 6, encoded.char_to_token(18)
+    assert_equal [3, 6], encoded.offsets[-3]
+
+    encoded = tokenizer.encode_batch([text], offsets: "utf16").first
+    assert_equal [18, 23], encoded.offsets[6]
+
+    error = assert_raises(ArgumentError) do
+      tokenizer.encode(text, offsets: :bad)
+    end
+    assert_equal "The offsets value must be 'char', 'byte', or 'utf16'", error.message
+  end
+
   def test_from_pretrained_namespace
     assert_kind_of Tokenizers::Tokenizer, Tokenizers.from_pretrained("google-bert/bert-base-cased")
   end
@@ -130,6 +161,283 @@ class TokenizerTest < Minitest::Test
     assert_equal [[0, 0]] * encoded[0].ids.size, encoded[0].offsets
   end
 
+  def test_encode_batch_interrupt
+    skip if stress?
+
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    input = ["I can feel the magic, can you?" * 100] * 100_000
+    thread = Thread.new { tokenizer.encode_batch(input) }
+    sleep(0.1)
+    thread.raise(Interrupt)
+    assert_raises(Interrupt) do
+      thread.join
+    end
+
+    expected_ids = [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102]
+    assert_equal expected_ids, tokenizer.encode("I can feel the magic, can you?").ids
+  end
+
+  def test_encode_batch_concurrent_write
+    skip if stress?
+
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    input = ["I can feel the magic, can you?" * 10] * 10_000
+    thread = Thread.new { tokenizer.encode_batch(input) }
+    sleep(0.01)
+    # waits for the batch without keeping it from finishing
+    tokenizer.add_tokens(["<new>"])
+    assert_equal 10_000, thread.value.size
+    assert tokenizer.token_to_id("<new>")
+  end
+
+  def test_train_progress
+    tokenizer = Tokenizers::Tokenizer.new(Tokenizers::Models::BPE.new(unk_token: "[UNK]"))
+    tokenizer.pre_tokenizer = Tokenizers::PreTokenizers::Whitespace.new
+    trainer = Tokenizers::Trainers::BpeTrainer.new(special_tokens: ["[UNK]"], show_progress: false)
+
+    events = []
+    Tempfile.create do |file|
+      file.write("I can feel the magic, can you?\n" * 100)
+      file.flush
+      tokenizer.train([file.path], trainer, progress: ->(stage, current, total) { events << [stage, current, total] })
+    end
+
+    assert_equal [:pre_processing, :training], events.map(&:first).uniq
+    pre_processing = events.select { |stage, _, _| stage == :pre_processing }
+    _, current, total = pre_processing.last
+    assert_equal total, current
+    assert_equal [[:training, 0, 1], [:training, 1, 1]], events.last(2)
+    assert tokenizer.token_to_id("magic")
+  end
+
+  def test_train_add_tokens
+    tokenizer = Tokenizers::Tokenizer.new(Tokenizers::Models::BPE.new(unk_token: "[UNK]"))
+    tokenizer.pre_tokenizer = Tokenizers::PreTokenizers::Whitespace.new
+    trainer = Tokenizers::Trainers::BpeTrainer.new(special_tokens: ["[UNK]"], show_progress: false)
+
+    Tempfile.create do |file|
+      file.write("I can feel the magic, can you?\n" * 100)
+      file.flush
+      # tokens added while training are kept
+      tokenizer.train([file.path], trainer, progress: ->(*) { tokenizer.add_tokens(["<new>"]) })
+    end
+
+    assert tokenizer.token_to_id("magic")
+    assert tokenizer.token_to_id("<new>")
+    assert tokenizer.token_to_id("[UNK]")
+  end
+
+  def test_encode_batch_packed
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+
+    batch = tokenizer.encode_batch_packed(["I can feel the magic, can you?", "hello"])
+    assert_kind_of Tokenizers::PackedBatch, batch
+    assert_equal "int64", batch.dtype
+    assert_equal [2, 11], batch.shape
+    assert_equal Encoding::BINARY, batch.ids.encoding
+    ids = batch.ids.unpack("q*")
+    assert_equal [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102], ids.first(11)
+    assert_equal [101, 19082, 102] + [0] * 8, ids.last(11)
+    assert_equal [1] * 11 + [1] * 3 + [0] * 8, batch.attention_mask.unpack("q*")
+    assert_equal [0] * 22, batch.type_ids.unpack("q*")
+    assert_same batch.ids, batch.ids
+    assert batch.ids.frozen?
+
+    batch = tokenizer.encode_batch_packed(["hello"], dtype: :int32)
+    assert_equal [101, 19082, 102], batch.ids.unpack("l*")
+
+    batch = tokenizer.encode_batch_packed(["hello", "I can feel the magic"], dtype: "uint16", padding: {length: 5, direction: "left"}, truncation: 5)
+    assert_equal [2, 5], batch.shape
+    assert_equal [0, 0, 101, 19082, 102, 101, 146, 1169, 1631, 102], batch.ids.unpack("S*")
+
+    assert_raises(ArgumentError) do
+      tokenizer.encode_batch_packed(["hello"], dtype: :float32)
+    end
+  end
+
+  def test_encode_for_model
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+
+    inputs = tokenizer.encode_for_model(["hello", "I can feel the magic"])
+    assert_equal [:input_ids, :token_type_ids, :attention_mask], inputs.keys
+    assert_equal [[101, 19082, 102, 0, 0, 0, 0], [101, 146, 1169, 1631, 1103, 3974, 102]], inputs[:input_ids]
+    assert_equal [[0] * 7] * 2, inputs[:token_type_ids]
+    assert_equal [[1, 1, 1, 0, 0, 0, 0], [1] * 7], inputs[:attention_mask]
+
+    tokenizer.enable_padding(direction: "left")
+    inputs = tokenizer.encode_for_model(["hello", "I can feel the magic"], return: [:input_ids, :position_ids])
+    assert_equal [0, 0, 0, 0, 101, 19082, 102], inputs[:input_ids][0]
+    assert_equal [[1, 1, 1, 1, 0, 1, 2], [0, 1, 2, 3, 4, 5, 6]], inputs[:position_ids]
+
+    inputs = tokenizer.encode_for_model(["hello"], return: ["input_ids"], dtype: :int64)
+    assert_equal [101, 19082, 102], inputs[:input_ids].unpack("q*")
+
+    assert_raises(ArgumentError) do
+      tokenizer.encode_for_model(["hello"], return: [:pixel_values])
+    end
+  end
+
+  def test_encode_fields
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    fields = {title: "I can feel the magic", body: "can you feel the magic, can you?"}
+
+    encoding = tokenizer.encode_fields(fields, max_length: 10)
+    assert_equal ["[CLS]", "I", "can", "feel", "[SEP]", "can", "you", "feel", "the", "[SEP]"], encoding.tokens
+    assert_equal [0, 0, 0, 0, 0, 1, 1, 1, 1, 1], encoding.type_ids
+
+    encoding = tokenizer.encode_fields(fields, max_length: 10, priorities: {body: 1})
+    assert_equal ["[CLS]", "[SEP]", "can", "you", "feel", "the", "magic", ",", "can", "[SEP]"], encoding.tokens
+
+    fields = {title: "I can", abstract: "feel the magic", body: "can you?"}
+    encoding = tokenizer.encode_fields(fields, max_length: 8, template: "[CLS] $title [SEP] $abstract [SEP] $body [SEP]")
+    assert_equal ["[CLS]", "I", "[SEP]", "feel", "the", "[SEP]", "can", "[SEP]"], encoding.tokens
+    assert_equal [0, 0, 0, 1, 1, 1, 2, 2], encoding.type_ids
+    assert_equal 3, encoding.n_sequences
+    assert_equal [5, 8], encoding.token_to_chars(4)
+
+    error = assert_raises(ArgumentError) do
+      tokenizer.encode_fields(fields, max_length: 8)
+    end
+    assert_equal "template is required for more than 2 fields", error.message
+    assert_raises(ArgumentError) do
+      tokenizer.encode_fields(fields, max_length: 3, template: "[CLS] $title [SEP] $abstract [SEP] $body [SEP]")
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.encode_fields(fields, max_length: 8, template: "[CLS] $title [SEP] $body [SEP]")
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.encode_fields({title: "I can"}, max_length: 8, priorities: {body: 1})
+    end
+  end
+
+  def test_count_tokens
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    assert_equal 11, tokenizer.count_tokens("I can feel the magic, can you?")
+    assert_equal 9, tokenizer.count_tokens("I can feel the magic, can you?", add_special_tokens: false)
+    assert_equal 2, tokenizer.count_tokens("")
+
+    tokenizer.enable_truncation(5)
+    tokenizer.enable_padding(length: 20)
+    assert_equal 11, tokenizer.count_tokens("I can feel the magic, can you?")
+    assert_equal [11, 3], tokenizer.count_tokens_batch(["I can feel the magic, can you?", "hello"])
+    assert_equal [9, 1], tokenizer.count_tokens_batch(["I can feel the magic, can you?", "hello"], add_special_tokens: false, threads: 2)
+
+    tokenizer.add_tokens(["can you"])
+    assert_equal 8, tokenizer.count_tokens("I can feel the magic, can you?", add_special_tokens: false)
+  end
+
+  def test_fit_messages
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    messages = [
+      {role: "system", content: "I can feel you."},
+      {role: "user", content: "I can feel the magic"},
+      {role: "assistant", content: "can you?"},
+      {role: "user", content: "feel the magic"}
+    ]
+
+    result = tokenizer.fit_messages(messages, max_tokens: 12, overhead: 0)
+    assert_equal [messages[0], messages[2], messages[3]], result[:messages]
+    assert_equal 11, result[:num_tokens]
+
+    result = tokenizer.fit_messages(messages, max_tokens: 12, overhead: 1)
+    assert_equal [messages[0], messages[3]], result[:messages]
+    assert_equal 10, result[:num_tokens]
+
+    result = tokenizer.fit_messages(messages, max_tokens: 12, overhead: 0, reserve: 4)
+    assert_equal [messages[0], messages[3]], result[:messages]
+    assert_equal 8, result[:num_tokens]
+
+    result = tokenizer.fit_messages(messages, max_tokens: 12, overhead: 0, keep: [:last])
+    assert_equal messages[1..], result[:messages]
+    assert_equal 11, result[:num_tokens]
+
+    # the last user message is kept, not a reply after it
+    reply = {role: "assistant", content: "I can feel you."}
+    result = tokenizer.fit_messages(messages + [reply], max_tokens: 3, overhead: 0, keep: [:last])
+    assert_equal [messages[3]], result[:messages]
+    assert_equal 3, result[:num_tokens]
+
+    # no overhead by default
+    result = tokenizer.fit_messages(messages, max_tokens: 12)
+    assert_equal [messages[0], messages[2], messages[3]], result[:messages]
+    assert_equal 11, result[:num_tokens]
+
+    assert_raises(Tokenizers::Error) do
+      tokenizer.fit_messages(messages, max_tokens: 7, overhead: 0)
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.fit_messages(messages, max_tokens: 12, overhead: 0, keep: [:bad])
+    end
+  end
+
+  def test_truncate_text
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    text = "I can feel   the magic, can you?"
+
+    assert_equal "I can feel   the", tokenizer.truncate_text(text, 4)
+    assert_equal "the magic, can you?", tokenizer.truncate_text(text, 6, side: :left)
+    assert_equal "I can you?", tokenizer.truncate_text(text, 4, side: :middle)
+    assert_equal text, tokenizer.truncate_text(text, 9)
+    assert_equal "", tokenizer.truncate_text(text, 0)
+
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    text = "Héllo wörld 😁!"
+    10.times do |n|
+      truncated = tokenizer.truncate_text(text, n)
+      assert text.start_with?(truncated)
+      assert_operator tokenizer.count_tokens(truncated), :<=, n
+    end
+
+    assert_raises(ArgumentError) do
+      tokenizer.truncate_text(text, 4, side: :top)
+    end
+  end
+
+  def test_chunk
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    text = "I can feel the magic. Can you?\n\nYes, I can feel it too."
+
+    chunks = tokenizer.chunk(text, max_tokens: 10)
+    assert_equal ["I can feel the magic. Can you?", "Yes, I can feel it too."], chunks.map { |c| c[:text] }
+    assert_equal [[0, 30], [32, 55]], chunks.map { |c| c[:offsets] }
+    assert_equal [9, 8], chunks.map { |c| c[:num_tokens] }
+    chunks.each do |c|
+      assert_equal c[:text], text[c[:offsets][0]...c[:offsets][1]]
+    end
+
+    chunks = tokenizer.chunk(text, max_tokens: 8, boundaries: [:sentence])
+    assert_equal ["I can feel the magic.", "Can you?", "Yes, I can feel it too."], chunks.map { |c| c[:text] }
+
+    chunks = tokenizer.chunk(text, max_tokens: 5, overlap: 2, boundaries: [])
+    assert_equal ["I can feel the magic", "the magic. Can you", "Can you?\n\nYes,"], chunks.first(3).map { |c| c[:text] }
+    assert chunks.all? { |c| c[:num_tokens] <= 5 }
+
+    assert_raises(ArgumentError) do
+      tokenizer.chunk(text, max_tokens: 5, overlap: 5)
+    end
+  end
+
+  def test_train_interrupt
+    skip if stress?
+
+    tokenizer = Tokenizers::Tokenizer.new(Tokenizers::Models::BPE.new(unk_token: "[UNK]"))
+    tokenizer.pre_tokenizer = Tokenizers::PreTokenizers::Whitespace.new
+    trainer = Tokenizers::Trainers::BpeTrainer.new(special_tokens: ["[UNK]"], show_progress: false)
+
+    Tempfile.create do |file|
+      file.write("I can feel the magic, can you?\n" * 1_000_000)
+      file.flush
+      # interrupted while reading the files
+      thread = Thread.new { tokenizer.train([file.path], trainer) }
+      sleep(0.1)
+      thread.raise(Interrupt)
+      assert_raises(Interrupt) do
+        thread.join
+      end
+    end
+    assert_nil tokenizer.token_to_id("magic")
+  end
+
   def test_decode_with_special_tokens
     tokenizer = Tokenizers.from_pretrained("bert-base-cased")
     token_ids = [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102]
@@ -215,6 +523,94 @@ class TokenizerTest < Minitest::Test
     assert_nil tokenizer.truncation
   end
 
+  def test_encode_truncation
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+
+    encoding = tokenizer.encode("I can feel the magic, can you?", truncation: 5)
+    assert_equal ["[CLS]", "I", "can", "feel", "[SEP]"], encoding.tokens
+    assert_nil tokenizer.truncation
+
+    options = {max_length: 5, direction: "left"}
+    encoding = tokenizer.encode("I can feel the magic, can you?", truncation: options)
+    assert_equal ["[CLS]", "can", "you", "?", "[SEP]"], encoding.tokens
+    assert_equal({max_length: 5, direction: "left"}, options)
+
+    tokenizer.enable_truncation(5)
+    assert_equal 11, tokenizer.encode("I can feel the magic, can you?", truncation: false).ids.size
+    assert_equal 5, tokenizer.encode("I can feel the magic, can you?").ids.size
+
+    assert_raises(ArgumentError) do
+      tokenizer.encode("hello", truncation: {stride: 1})
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.encode("hello", truncation: {max_length: 5, stride: 5})
+    end
+  end
+
+  def test_encode_truncation_middle
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+
+    encoding = tokenizer.encode("I can feel the magic, can you?", truncation: {max_length: 7, strategy: "middle", stride: 1})
+    assert_equal ["[CLS]", "I", "can", "feel", "you", "?", "[SEP]"], encoding.tokens
+    assert_equal [[0, 0], [0, 1], [2, 5], [6, 10], [26, 29], [29, 30], [0, 0]], encoding.offsets
+    assert_equal 1, encoding.overflowing.size
+    assert_equal ["[CLS]", "feel", "the", "magic", ",", "can", "you", "[SEP]"], encoding.overflowing[0].tokens
+
+    encoding = tokenizer.encode("I can feel the magic, can you?", truncation: {max_length: 7, strategy: "middle", head_ratio: 0.25})
+    assert_equal ["[CLS]", "I", ",", "can", "you", "?", "[SEP]"], encoding.tokens
+
+    encoding = tokenizer.encode("I can feel the magic, can you?", "can you feel the magic?", truncation: {max_length: 12, strategy: "middle"})
+    assert_equal ["[CLS]", "I", "can", "feel", "you", "?", "[SEP]", "can", "you", "magic", "?", "[SEP]"], encoding.tokens
+    assert_equal [0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1], encoding.type_ids
+
+    tokenizer.enable_truncation(7, strategy: "middle", head_ratio: 0.75)
+    assert_equal({"max_length" => 7, "stride" => 0, "strategy" => "middle", "head_ratio" => 0.75}, tokenizer.truncation)
+    encodings = tokenizer.encode_batch(["I can feel the magic, can you?", "hello"], padding: true)
+    assert_equal ["[CLS]", "I", "can", "feel", "the", "?", "[SEP]"], encodings[0].tokens
+    assert_equal ["[CLS]", "hello", "[SEP]", "[PAD]", "[PAD]", "[PAD]", "[PAD]"], encodings[1].tokens
+    assert_equal 11, tokenizer.encode("I can feel the magic, can you?", truncation: false).ids.size
+
+    [Tokenizers::Tokenizer.from_str(tokenizer.to_s), Marshal.load(Marshal.dump(tokenizer))].each do |copy|
+      assert_equal tokenizer.truncation, copy.truncation
+      assert_equal encodings[0].tokens, copy.encode("I can feel the magic, can you?").tokens
+    end
+    Tempfile.create(["tokenizer", ".json"]) do |file|
+      tokenizer.save(file.path)
+      assert_equal tokenizer.truncation, Tokenizers::Tokenizer.from_file(file.path).truncation
+    end
+    tokenizer.no_truncation
+    refute_includes tokenizer.to_s, "middle_truncation"
+    assert_nil tokenizer.truncation
+
+    assert_raises(ArgumentError) do
+      tokenizer.encode("hello", truncation: {max_length: 5, head_ratio: 0.5})
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.encode("hello", truncation: {max_length: 5, strategy: "middle", head_ratio: 2})
+    end
+    assert_raises(ArgumentError) do
+      tokenizer.encode("hello", truncation: {max_length: 5, strategy: "middle", stride: 5})
+    end
+  end
+
+  def test_encode_padding
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+
+    encoding = tokenizer.encode("hello", padding: {length: 5})
+    assert_equal ["[CLS]", "hello", "[SEP]", "[PAD]", "[PAD]"], encoding.tokens
+    assert_equal [1, 1, 1, 0, 0], encoding.attention_mask
+    assert_nil tokenizer.padding
+
+    encodings = tokenizer.encode_batch(["hello", "I can feel the magic"], padding: true, truncation: 4)
+    assert_equal [["[CLS]", "hello", "[SEP]", "[PAD]"], ["[CLS]", "I", "can", "[SEP]"]], encodings.map(&:tokens)
+
+    encodings = tokenizer.encode_batch_fast(["hello", "I can feel the magic"], padding: {direction: "left"})
+    assert_equal [0, 0, 0, 0, 1, 1, 1], encodings[0].attention_mask
+
+    tokenizer.enable_padding(length: 10)
+    assert_equal 3, tokenizer.encode("hello", padding: false).ids.size
+  end
+
   def test_serialization
     skip "vocab method performs many allocations" if stress?
 
@@ -242,6 +638,77 @@ class TokenizerTest < Minitest::Test
     end
   end
 
+  def test_marshal
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    tokenizer.add_tokens(["mellifluous"])
+    tokenizer.enable_truncation(8, stride: 2)
+    encoded = tokenizer.encode("I can feel the mellifluous magic, can you?")
+
+    new_tokenizer = Marshal.load(Marshal.dump(tokenizer))
+    assert_kind_of Tokenizers::Tokenizer, new_tokenizer
+    assert_equal tokenizer.truncation, new_tokenizer.truncation
+    new_encoded = new_tokenizer.encode("I can feel the mellifluous magic, can you?")
+    assert_equal encoded.ids, new_encoded.ids
+    assert_equal encoded.offsets, new_encoded.offsets
+
+    new_encoded = Marshal.load(Marshal.dump(encoded))
+    assert_kind_of Tokenizers::Encoding, new_encoded
+    assert_equal encoded.ids, new_encoded.ids
+    assert_equal encoded.tokens, new_encoded.tokens
+    assert_equal encoded.offsets, new_encoded.offsets
+    assert_equal encoded.sequence_ids, new_encoded.sequence_ids
+    assert_equal encoded.overflowing.map(&:ids), new_encoded.overflowing.map(&:ids)
+  end
+
+  def test_make_shareable
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    assert_same tokenizer, tokenizer.make_shareable
+    assert tokenizer.frozen?
+    assert Ractor.shareable?(tokenizer)
+
+    error = assert_raises(FrozenError) do
+      tokenizer.enable_padding
+    end
+    assert_equal "can't modify frozen Tokenizers::Tokenizer", error.message
+    assert_nil tokenizer.padding
+
+    decoder = tokenizer.decoder
+    assert decoder.frozen?
+    assert Ractor.shareable?(decoder)
+    assert tokenizer.model.frozen?
+
+    error = assert_raises(FrozenError) do
+      decoder.prefix = "@@"
+    end
+    assert_equal "can't modify frozen Tokenizers::Decoders::WordPiece", error.message
+    assert_equal "##", tokenizer.decoder.prefix
+    assert_raises(FrozenError) do
+      tokenizer.model.unk_token = "[PAD]"
+    end
+
+    expected_ids = [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102]
+    assert_equal expected_ids, tokenizer.encode("I can feel the magic, can you?").ids
+  end
+
+  def test_ractor
+    skip if stress?
+
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased").make_shareable
+    ractors =
+      2.times.map do
+        Ractor.new(tokenizer) do |t|
+          ids = t.encode("I can feel the magic, can you?").ids
+          [ids, t.decode(ids)]
+        end
+      end
+    expected_ids = [101, 146, 1169, 1631, 1103, 3974, 117, 1169, 1128, 136, 102]
+    ractors.each do |ractor|
+      ids, text = ractor.respond_to?(:value) ? ractor.value : ractor.take
+      assert_equal expected_ids, ids
+      assert_equal "I can feel the magic, can you?", text
+    end
+  end
+
   def test_num_special_tokens_to_add
     tokenizer = Tokenizers.from_pretrained("bert-base-cased")
     assert_equal 3, tokenizer.num_special_tokens_to_add(true)
@@ -265,4 +732,168 @@ class TokenizerTest < Minitest::Test
 
     assert_equal "Ruby", tokenizer.decoder.decode(["Ruby"])
   end
+
+  def test_token_bytes
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    assert_equal " hello".b, tokenizer.token_bytes(tokenizer.token_to_id("Ġhello"))
+    assert_equal "\xC3".b, tokenizer.token_bytes(tokenizer.token_to_id("Ã"))
+    assert_equal Encoding::BINARY, tokenizer.token_bytes(0).encoding
+    assert_nil tokenizer.token_bytes(100000)
+
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    assert_equal "ing", tokenizer.token_bytes(tokenizer.token_to_id("##ing"))
+    assert_equal " hello", tokenizer.token_bytes(tokenizer.token_to_id("hello"))
+
+    model = Tokenizers::Models::BPE.new(vocab: {"<0xE2>" => 0, "a" => 1}, merges: [])
+    tokenizer = Tokenizers::Tokenizer.new(model)
+    assert_equal "<0xE2>", tokenizer.token_bytes(0)
+    tokenizer.decoder = Tokenizers::Decoders::ByteFallback.new
+    assert_equal "\xE2".b, tokenizer.token_bytes(0)
+    assert_equal "a", tokenizer.token_bytes(1)
+  end
+
+  def test_vocab_bytes
+    skip "vocab_bytes method performs many allocations" if stress?
+
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    vocab = tokenizer.vocab_bytes
+    assert_equal tokenizer.vocab_size, vocab.size
+    assert_equal " hello".b, vocab[tokenizer.token_to_id("Ġhello")]
+  end
+
+  def test_prefix_index
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    index = tokenizer.prefix_index
+
+    ids = index.tokens_with_prefix(" http")
+    assert_includes ids, tokenizer.token_to_id("Ġhttp")
+    assert_includes ids, tokenizer.token_to_id("Ġhttps")
+    refute_includes ids, tokenizer.token_to_id("Ġh")
+    assert_equal ids.sort, ids
+
+    ids = index.prefix_tokens_of(" https")
+    assert_includes ids, tokenizer.token_to_id("Ġh")
+    assert_includes ids, tokenizer.token_to_id("Ġhttp")
+    assert_includes ids, tokenizer.token_to_id("Ġhttps")
+  end
+
+  def test_heal
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    ids = tokenizer.encode("The link is http").ids
+
+    result = tokenizer.heal(ids)
+    assert_equal ids[0..-2], result[:ids]
+    assert_equal " http".b, result[:prefix]
+
+    constraint = result[:constraint]
+    state = constraint.initial_state
+    allowed = constraint.allowed_token_ids(state)
+    assert_includes allowed, tokenizer.token_to_id("Ġhttps")
+    refute_includes allowed, tokenizer.token_to_id("Ġis")
+    state = constraint.advance(state, tokenizer.token_to_id("Ġhttps"))
+    assert constraint.final?(state)
+
+    result = tokenizer.heal([], index: tokenizer.prefix_index)
+    assert_empty result[:ids]
+    assert_equal "", result[:prefix]
+    assert result[:constraint].final?(0)
+  end
+
+  def test_heal_special_tokens
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    constraint = tokenizer.heal(tokenizer.encode("The link is http").ids)[:constraint]
+    state = constraint.advance(constraint.initial_state, tokenizer.token_to_id("Ġhttps"))
+    allowed = constraint.allowed_token_ids(state)
+    assert_includes allowed, tokenizer.token_to_id("hello")
+    refute_includes allowed, tokenizer.token_to_id("<|endoftext|>")
+  end
+
+  def test_prefix_index_changes
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    assert_empty tokenizer.prefix_index.tokens_with_prefix("xyzzy")
+    tokenizer.add_tokens(["xyzzy"])
+    assert_equal [tokenizer.token_to_id("xyzzy")], tokenizer.prefix_index.tokens_with_prefix(" xyzzy")
+
+    assert_includes tokenizer.prefix_index.tokens_with_prefix("ing"), tokenizer.token_to_id("##ing")
+    tokenizer.decoder.prefix = "@@"
+    refute_includes tokenizer.prefix_index.tokens_with_prefix("ing"), tokenizer.token_to_id("##ing")
+  end
+
+  def test_stop_sequence_matcher
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    matcher = tokenizer.stop_sequence_matcher(["\n\nUser:", "<|endoftext|>"])
+
+    text = +""
+    stop = nil
+    tokenizer.encode("Hi there\n\nUser: more").ids.each do |id|
+      result = matcher.push(id)
+      text << result[:text]
+      if result[:stop]
+        stop = result[:stop]
+        break
+      end
+    end
+    assert_equal "Hi there", text
+    assert_equal "\n\nUser:", stop
+    assert matcher.stopped?
+    assert_raises(Tokenizers::Error) do
+      matcher.push(0)
+    end
+
+    matcher = tokenizer.stop_sequence_matcher(["<|endoftext|>"])
+    assert_equal({text: "Hi", stop: nil}, matcher.push(tokenizer.token_to_id("Hi")))
+    assert_equal({text: "", stop: "<|endoftext|>"}, matcher.push(tokenizer.token_to_id("<|endoftext|>")))
+  end
+
+  def test_stop_sequence_matcher_decode
+    vocab = {"▁Hello" => 0, "▁world" => 1, "!" => 2, "[UNK]" => 3}
+    tokenizer = Tokenizers::Tokenizer.new(Tokenizers::Models::WordLevel.new(vocab: vocab, unk_token: "[UNK]"))
+    tokenizer.decoder = Tokenizers::Decoders::Metaspace.new
+    tokenizer.add_special_tokens(["</s>"])
+    ids = [tokenizer.token_to_id("</s>"), 0, 1, 2]
+
+    matcher = tokenizer.stop_sequence_matcher(["\n"])
+    text = ids.map { |id| matcher.push(id)[:text] }.join + matcher.flush
+    assert_equal "Hello world!", tokenizer.decode(ids)
+    assert_equal tokenizer.decode(ids), text
+
+    matcher = tokenizer.stop_sequence_matcher(["</s>"])
+    assert_equal({text: "Hello", stop: nil}, matcher.push(0))
+    assert_equal({text: "", stop: "</s>"}, matcher.push(tokenizer.token_to_id("</s>")))
+  end
+
+  def test_stop_sequence_matcher_flush
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    matcher = tokenizer.stop_sequence_matcher(["world!"])
+
+    texts = tokenizer.encode("Hello wor").ids.map { |id| matcher.push(id)[:text] }
+    assert_equal ["Hello", " "], texts
+    assert_equal "wor", matcher.flush
+    refute matcher.stopped?
+
+    assert_raises(ArgumentError) do
+      tokenizer.stop_sequence_matcher([""])
+    end
+  end
+
+  def test_token_variants
+    tokenizer = Tokenizers.from_pretrained("gpt2")
+    variants = tokenizer.token_variants("hello")
+    assert_includes variants, [tokenizer.token_to_id("hello")]
+    assert_includes variants, [tokenizer.token_to_id("Ġhello")]
+    assert_includes variants, [tokenizer.token_to_id("Hello")]
+    assert_includes variants, [tokenizer.token_to_id("ĠHello")]
+    assert_equal variants.uniq, variants
+
+    variants = tokenizer.token_variants("hello", casing: false, spacing: false)
+    assert_equal [[tokenizer.token_to_id("hello")]], variants
+
+    tokenizer = Tokenizers.from_pretrained("bert-base-cased")
+    variants = tokenizer.token_variants("hello")
+    assert_equal [[tokenizer.token_to_id("hello")], [tokenizer.token_to_id("Hello")]], variants
+
+    assert_raises(ArgumentError) do
+      tokenizer.token_variants("")
+    end
+  end
 end
@@ -164,4 +164,12 @@ class TrainerTest < Minitest::Test
     trainer.end_of_word_suffix = "#x#"
     assert_equal "#x#", trainer.end_of_word_suffix
   end
+
+  def test_marshal
+    trainer = Tokenizers::Trainers::BpeTrainer.new(vocab_size: 500, special_tokens: ["[UNK]"])
+    new_trainer = Marshal.load(Marshal.dump(trainer))
+    assert_instance_of Tokenizers::Trainers::BpeTrainer, new_trainer
+    assert_equal 500, new_trainer.vocab_size
+    assert_equal ["[UNK]"], new_trainer.special_tokens
+  end
 end